@@ -0,0 +1,78 @@
+//! Parsing of the met.no response: the typed models against
+//! the walk over `serde_json::Value` they replaced
+
+use chrono::DateTime;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde_json::Value;
+use weather_bot::metno;
+use weather_bot::weather::{Forecast, Forecasts, Hours, NextHours, NextHoursForecast};
+use weather_bot::weather_codes::WeatherSymbol;
+
+const RESPONSE: &str = include_str!("../tests/fixtures/response.json");
+
+fn parse_next_hours(next: &Value) -> Option<NextHours> {
+    let details = &next["details"];
+    Some(NextHours {
+        symbol: WeatherSymbol::from(next["summary"]["symbol_code"].as_str()?),
+        precipitation_amount: details["precipitation_amount"].as_f64(),
+        precipitation_amount_min: details["precipitation_amount_min"].as_f64(),
+        precipitation_amount_max: details["precipitation_amount_max"].as_f64(),
+        probability_of_precipitation: details["probability_of_precipitation"].as_f64(),
+    })
+}
+
+fn parse_value(text: &str) -> Option<Forecasts> {
+    let v: Value = serde_json::from_str(text).ok()?;
+    v["properties"]["meta"]["updated_at"].as_str()?;
+    v["properties"]["timeseries"]
+        .as_array()?
+        .iter()
+        .map(|entry| {
+            let time = DateTime::parse_from_rfc3339(entry["time"].as_str()?).ok()?;
+            let values = &entry["data"]["instant"]["details"];
+            let forecast_by_time: NextHoursForecast = [
+                (Hours::Hour1, "next_1_hours"),
+                (Hours::Hour6, "next_6_hours"),
+                (Hours::Hour12, "next_12_hours"),
+            ]
+            .into_iter()
+            .filter_map(|(hours, key)| Some((hours, parse_next_hours(&entry["data"][key])?)))
+            .collect();
+
+            let forecast = Forecast {
+                air_pressure_at_sea_level: values["air_pressure_at_sea_level"].as_f64()?,
+                air_temperature: values["air_temperature"].as_f64()?,
+                cloud_area_fraction: values["cloud_area_fraction"].as_f64()?,
+                relative_humidity: values["relative_humidity"].as_f64()?,
+                wind_from_direction: values["wind_from_direction"].as_f64()?,
+                wind_speed: values["wind_speed"].as_f64()?,
+                wind_speed_of_gust: values["wind_speed_of_gust"].as_f64(),
+                dew_point_temperature: values["dew_point_temperature"].as_f64(),
+                fog_area_fraction: values["fog_area_fraction"].as_f64(),
+                ultraviolet_index_clear_sky: values["ultraviolet_index_clear_sky"].as_f64(),
+                cloud_area_fraction_high: values["cloud_area_fraction_high"].as_f64(),
+                cloud_area_fraction_medium: values["cloud_area_fraction_medium"].as_f64(),
+                cloud_area_fraction_low: values["cloud_area_fraction_low"].as_f64(),
+                forecast_by_time,
+            };
+            Some((time, forecast))
+        })
+        .collect()
+}
+
+fn parse_typed(text: &str) -> Option<Forecasts> {
+    metno::parse_response(text).ok()
+}
+
+fn parse_response(c: &mut Criterion) {
+    // Both parsers must take the same values, or the comparison is meaningless
+    assert_eq!(parse_value(RESPONSE), parse_typed(RESPONSE));
+
+    let mut group = c.benchmark_group("metno_response");
+    group.bench_function("value", |b| b.iter(|| parse_value(black_box(RESPONSE))));
+    group.bench_function("typed", |b| b.iter(|| parse_typed(black_box(RESPONSE))));
+    group.finish();
+}
+
+criterion_group!(benches, parse_response);
+criterion_main!(benches);
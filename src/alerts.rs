@@ -0,0 +1,649 @@
+use log::{error, info};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+
+use crate::subscriptions::SubscriptionStorage;
+use weather_bot::error::RequestError;
+use weather_bot::geo::GeoCoordinate;
+use weather_bot::http::HttpClient;
+use weather_bot::storage::{AsyncStorage, Storage};
+
+pub type SentAlertStorage = Arc<dyn Storage<SentAlertKey, SentAlert>>;
+
+/// How often the alert source is polled
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Sent alerts are forgotten this long after they expire
+const KEEP_EXPIRED_HOURS: i64 = 24;
+/// Sent alerts without the expiry time are forgotten this long after sending
+const KEEP_UNDATED_DAYS: i64 = 7;
+
+/// Severity of the alert as defined by CAP, ordered from the lowest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Unknown,
+    Minor,
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+/// CAP message type, cancellations are never announced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Alert,
+    Update,
+    Cancel,
+}
+
+/// Weather warning for an area
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub identifier: String,
+    pub message_type: MessageType,
+    /// Identifiers of the earlier messages this one updates
+    pub references: Vec<String>,
+    pub severity: Severity,
+    pub event: String,
+    pub headline: Option<String>,
+    pub area: String,
+    pub onset: Option<DateTime<FixedOffset>>,
+    pub expires: Option<DateTime<FixedOffset>>,
+}
+
+/// An alert is sent to a chat only once
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SentAlertKey {
+    pub chat_id: i64,
+    pub identifier: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentAlert {
+    pub severity: Severity,
+    pub expires: Option<DateTime<FixedOffset>>,
+    /// The alerts saved before it are counted from the first load
+    #[serde(default = "Utc::now")]
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Source of the active weather alerts for a location
+#[async_trait]
+pub trait AlertSource: Send + Sync {
+    /// The alerts with the `known` identifiers are not fetched again
+    async fn get_alerts(
+        &self,
+        coordinates: &GeoCoordinate,
+        known: &HashSet<String>,
+    ) -> Result<Vec<Alert>, RequestError>;
+}
+
+/// Alerts of the Norwegian Meteorological Institute
+#[derive(Debug, Clone)]
+pub struct MetAlerts {
+    client: HttpClient,
+}
+
+impl MetAlerts {
+    pub fn new(client: HttpClient) -> Self {
+        Self { client }
+    }
+
+    async fn request_text(&self, api_url: &str) -> Result<String, RequestError> {
+        info!("Request API: {}...", api_url);
+
+        let res = self.client.send(self.client.get(api_url)).await?;
+        let status = res.status();
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        Ok(res.text().await?)
+    }
+}
+
+#[async_trait]
+impl AlertSource for MetAlerts {
+    async fn get_alerts(
+        &self,
+        coordinates: &GeoCoordinate,
+        known: &HashSet<String>,
+    ) -> Result<Vec<Alert>, RequestError> {
+        let api_url = format!(
+            "https://api.met.no/weatherapi/metalerts/2.0/current.rss?lat={}&lon={}",
+            coordinates.latitude, coordinates.longitude
+        );
+        let feed = self.request_text(&api_url).await?;
+
+        let mut alerts = Vec::new();
+        for item in parse_rss_feed(&feed)? {
+            if item
+                .identifier
+                .as_ref()
+                .is_some_and(|identifier| known.contains(identifier))
+            {
+                continue;
+            }
+            // A broken document must not hide the other alerts
+            let alert = self
+                .request_text(&item.cap_url)
+                .await
+                .and_then(|cap| parse_cap_alert(&cap));
+            match alert {
+                Ok(alert) => alerts.push(alert),
+                Err(err) => error!("Can't get the alert {}: {}", item.cap_url, err),
+            }
+        }
+
+        Ok(alerts)
+    }
+}
+
+#[derive(Deserialize)]
+struct Rss {
+    channel: RssChannel,
+}
+
+#[derive(Deserialize)]
+struct RssChannel {
+    #[serde(rename = "item", default)]
+    items: Vec<RssItem>,
+}
+
+#[derive(Deserialize)]
+struct RssItem {
+    guid: Option<RssGuid>,
+    link: Option<String>,
+    enclosure: Option<RssEnclosure>,
+}
+
+#[derive(Deserialize)]
+struct RssGuid {
+    #[serde(rename = "$text")]
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct RssEnclosure {
+    #[serde(rename = "@url")]
+    url: String,
+}
+
+/// CAP document of the feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    /// The guid of met.no is the identifier of the CAP message
+    pub identifier: Option<String>,
+    pub cap_url: String,
+}
+
+/// Returns the CAP documents of the feed items
+pub fn parse_rss_feed(xml: &str) -> Result<Vec<FeedItem>, RequestError> {
+    let rss: Rss = quick_xml::de::from_str(xml)?;
+
+    Ok(rss
+        .channel
+        .items
+        .into_iter()
+        .filter_map(|item| {
+            Some(FeedItem {
+                identifier: item.guid.map(|guid| guid.value),
+                cap_url: item
+                    .enclosure
+                    .map(|enclosure| enclosure.url)
+                    .or(item.link)?,
+            })
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct CapAlert {
+    identifier: String,
+    #[serde(rename = "msgType")]
+    msg_type: String,
+    references: Option<String>,
+    #[serde(rename = "info", default)]
+    infos: Vec<CapInfo>,
+}
+
+#[derive(Deserialize)]
+struct CapInfo {
+    language: Option<String>,
+    event: String,
+    severity: String,
+    #[serde(rename = "eventCode", default)]
+    event_codes: Vec<CapValue>,
+    onset: Option<String>,
+    expires: Option<String>,
+    headline: Option<String>,
+    #[serde(rename = "area", default)]
+    areas: Vec<CapArea>,
+}
+
+#[derive(Deserialize)]
+struct CapValue {
+    #[serde(rename = "valueName")]
+    value_name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct CapArea {
+    #[serde(rename = "areaDesc")]
+    area_desc: String,
+}
+
+fn parse_cap_time(value: Option<String>) -> Result<Option<DateTime<FixedOffset>>, RequestError> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .map_err(|_| RequestError::Parse(format!("invalid CAP time \"{}\"", value)))
+        })
+        .transpose()
+}
+
+/// Parse a CAP 1.2 message, the English info block is preferred
+pub fn parse_cap_alert(xml: &str) -> Result<Alert, RequestError> {
+    let cap: CapAlert = quick_xml::de::from_str(xml)?;
+
+    let message_type = match cap.msg_type.as_str() {
+        "Alert" => MessageType::Alert,
+        "Update" => MessageType::Update,
+        "Cancel" => MessageType::Cancel,
+        other => {
+            return Err(RequestError::Parse(format!(
+                "unknown CAP msgType \"{}\"",
+                other
+            )))
+        }
+    };
+    // References are "sender,identifier,sent" triples separated by spaces
+    let references = cap
+        .references
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|reference| reference.split(',').nth(1))
+        .map(str::to_string)
+        .collect();
+
+    let english = cap.infos.iter().position(|info| {
+        info.language
+            .as_deref()
+            .is_some_and(|language| language.starts_with("en"))
+    });
+    let info = match english {
+        Some(index) => cap.infos.into_iter().nth(index),
+        None => cap.infos.into_iter().next(),
+    }
+    .ok_or_else(|| RequestError::Parse("CAP info is missing".to_string()))?;
+
+    let severity = match info.severity.as_str() {
+        "Minor" => Severity::Minor,
+        "Moderate" => Severity::Moderate,
+        "Severe" => Severity::Severe,
+        "Extreme" => Severity::Extreme,
+        _ => Severity::Unknown,
+    };
+    let event = info
+        .event_codes
+        .into_iter()
+        .find(|code| code.value_name == "eventType")
+        .map(|code| code.value)
+        .unwrap_or(info.event);
+    let area = info
+        .areas
+        .into_iter()
+        .map(|area| area.area_desc)
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    Ok(Alert {
+        identifier: cap.identifier,
+        message_type,
+        references,
+        severity,
+        event,
+        headline: info.headline,
+        area,
+        onset: parse_cap_time(info.onset)?,
+        expires: parse_cap_time(info.expires)?,
+    })
+}
+
+impl Severity {
+    fn emoji(&self) -> &'static str {
+        match self {
+            Severity::Unknown | Severity::Minor => "ℹ️",
+            Severity::Moderate => "🟡",
+            Severity::Severe => "🟠",
+            Severity::Extreme => "🔴",
+        }
+    }
+}
+
+/// Whether an alert is new or an upgrade of an already sent one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertChange {
+    New,
+    Upgraded,
+}
+
+/// Decide if the alert should be sent, `previous` is the highest severity
+/// already sent for the alerts it references
+pub fn alert_change(alert: &Alert, previous: Option<Severity>) -> Option<AlertChange> {
+    match (alert.message_type, previous) {
+        (MessageType::Cancel, _) => None,
+        (_, None) => Some(AlertChange::New),
+        (_, Some(previous)) if alert.severity > previous => Some(AlertChange::Upgraded),
+        _ => None,
+    }
+}
+
+pub fn display_alert_telegram(alert: &Alert, change: AlertChange) -> String {
+    let title = match change {
+        AlertChange::New => "Weather warning",
+        AlertChange::Upgraded => "Weather warning upgraded",
+    };
+    let time_format = "%d %b %H:%M";
+    let validity = match (alert.onset, alert.expires) {
+        (Some(onset), Some(expires)) => format!(
+            "{} – {}",
+            onset.format(time_format),
+            expires.format(time_format)
+        ),
+        (Some(onset), None) => format!("from {}", onset.format(time_format)),
+        (None, Some(expires)) => format!("until {}", expires.format(time_format)),
+        (None, None) => "until further notice".to_string(),
+    };
+
+    let mut message = format!(
+        "{} {}: {}\nSeverity: {:?}\nArea: {}\nValid: {}",
+        alert.severity.emoji(),
+        title,
+        alert.event,
+        alert.severity,
+        alert.area,
+        validity
+    );
+    if let Some(headline) = &alert.headline {
+        message.push_str(&format!("\n{}", headline));
+    }
+    message
+}
+
+/// Check the alert against the sent ones, send it and remember it
+async fn notify_chat(bot: &Bot, chat_id: i64, alert: &Alert, sent: &SentAlertStorage) {
+    let key = SentAlertKey {
+        chat_id,
+        identifier: alert.identifier.clone(),
+    };
+    match sent.get(&key) {
+        Ok(Some(_)) => return,
+        Ok(None) => {}
+        Err(err) => {
+            error!("Can't load the sent alert: {}", err);
+            return;
+        }
+    }
+
+    let previous = alert
+        .references
+        .iter()
+        .filter_map(|identifier| {
+            sent.get(&SentAlertKey {
+                chat_id,
+                identifier: identifier.clone(),
+            })
+            .ok()
+            .flatten()
+        })
+        .map(|sent_alert| sent_alert.severity)
+        .max();
+
+    if let Some(change) = alert_change(alert, previous) {
+        info!("Send the alert {} to {}", alert.identifier, chat_id);
+        let text = display_alert_telegram(alert, change);
+        if let Err(err) = bot.send_message(ChatId(chat_id), text).await {
+            error!("Can't send the alert to {}: {}", chat_id, err);
+            return;
+        }
+    }
+
+    // Downgrades are remembered with the highest sent severity
+    let sent_alert = SentAlert {
+        severity: previous.map_or(alert.severity, |previous| previous.max(alert.severity)),
+        expires: alert.expires,
+        sent_at: Utc::now(),
+    };
+    if let Err(err) = sent.set_async(key, sent_alert).await {
+        error!("Can't save the sent alert: {}", err);
+    }
+}
+
+async fn forget_expired(sent: &SentAlertStorage, now: DateTime<Utc>) {
+    if let Err(err) = sent
+        .retain_async(move |_, sent_alert| !is_forgotten(sent_alert, &now))
+        .await
+    {
+        error!("Can't remove the sent alerts: {}", err);
+    }
+}
+
+fn is_forgotten(sent_alert: &SentAlert, now: &DateTime<Utc>) -> bool {
+    match sent_alert.expires {
+        Some(expires) => now.signed_duration_since(expires).num_hours() >= KEEP_EXPIRED_HOURS,
+        None => now.signed_duration_since(sent_alert.sent_at).num_days() >= KEEP_UNDATED_DAYS,
+    }
+}
+
+/// Identifiers of the alerts already sent to every chat, they are not fetched
+fn known_alerts(all_sent: &[(SentAlertKey, SentAlert)], chats: &[i64]) -> HashSet<String> {
+    let mut known: HashSet<String> = all_sent
+        .iter()
+        .map(|(key, _)| key.identifier.clone())
+        .collect();
+    known.retain(|identifier| {
+        chats.iter().all(|chat_id| {
+            all_sent
+                .iter()
+                .any(|(key, _)| key.chat_id == *chat_id && key.identifier == *identifier)
+        })
+    });
+    known
+}
+
+async fn send_new_alerts(
+    bot: &Bot,
+    source: &dyn AlertSource,
+    subscriptions: &SubscriptionStorage,
+    sent: &SentAlertStorage,
+) {
+    let all = match subscriptions.all() {
+        Ok(all) => all,
+        Err(err) => {
+            error!("Can't load the subscriptions: {}", err);
+            return;
+        }
+    };
+
+    // Locations shared by several chats are requested once
+    let mut locations: Vec<(GeoCoordinate, Vec<i64>)> = Vec::new();
+    for (key, subscription) in all {
+        match locations
+            .iter_mut()
+            .find(|(coordinates, _)| *coordinates == subscription.coordinates)
+        {
+            Some((_, chats)) if chats.contains(&key.chat_id) => {}
+            Some((_, chats)) => chats.push(key.chat_id),
+            None => locations.push((subscription.coordinates, vec![key.chat_id])),
+        }
+    }
+
+    let all_sent = match sent.all() {
+        Ok(all_sent) => all_sent,
+        Err(err) => {
+            error!("Can't load the sent alerts: {}", err);
+            Vec::new()
+        }
+    };
+    for (coordinates, chats) in locations {
+        let known = known_alerts(&all_sent, &chats);
+        let alerts = match source.get_alerts(&coordinates, &known).await {
+            Ok(alerts) => alerts,
+            Err(err) => {
+                error!("Can't get the alerts for {:?}: {}", coordinates, err);
+                continue;
+            }
+        };
+
+        for alert in &alerts {
+            for chat_id in &chats {
+                notify_chat(bot, *chat_id, alert, sent).await;
+            }
+        }
+    }
+
+    forget_expired(sent, Utc::now()).await;
+}
+
+/// Send the alerts for the subscribed locations, never returns
+pub async fn run(
+    bot: Bot,
+    source: Arc<dyn AlertSource>,
+    subscriptions: SubscriptionStorage,
+    sent: SentAlertStorage,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        send_new_alerts(&bot, source.as_ref(), &subscriptions, &sent).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = include_str!("../tests/fixtures/metalerts/current.rss");
+    const ALERT: &str = include_str!("../tests/fixtures/metalerts/alert.xml");
+    const UPDATE: &str = include_str!("../tests/fixtures/metalerts/update.xml");
+
+    #[test]
+    fn feed_links_to_cap_documents() {
+        let items = parse_rss_feed(FEED).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].identifier.as_deref(),
+            Some("2.49.0.1.578.0.20231018081025.050")
+        );
+        assert!(items[0]
+            .cap_url
+            .ends_with("cap=2.49.0.1.578.0.20231018081025.050&format=xml"));
+    }
+
+    #[test]
+    fn alerts_sent_to_every_chat_are_known() {
+        let sent_to = |chat_id: i64, identifier: &str| {
+            (
+                SentAlertKey {
+                    chat_id,
+                    identifier: identifier.to_string(),
+                },
+                SentAlert {
+                    severity: Severity::Moderate,
+                    expires: None,
+                    sent_at: Utc::now(),
+                },
+            )
+        };
+        let all_sent = vec![sent_to(1, "a"), sent_to(2, "a"), sent_to(1, "b")];
+
+        assert_eq!(
+            known_alerts(&all_sent, &[1, 2]),
+            HashSet::from(["a".to_string()])
+        );
+        assert_eq!(known_alerts(&all_sent, &[1]).len(), 2);
+        assert!(known_alerts(&all_sent, &[3]).is_empty());
+    }
+
+    #[test]
+    fn sent_alerts_are_forgotten() {
+        let now = Utc::now();
+        let expires = DateTime::parse_from_rfc3339("2023-10-20T00:00:00+00:00").unwrap();
+        let sent_alert = SentAlert {
+            severity: Severity::Severe,
+            expires: Some(expires),
+            sent_at: now,
+        };
+        let expired = expires.with_timezone(&Utc);
+        assert!(!is_forgotten(
+            &sent_alert,
+            &(expired + chrono::Duration::hours(23))
+        ));
+        assert!(is_forgotten(
+            &sent_alert,
+            &(expired + chrono::Duration::hours(24))
+        ));
+
+        let undated = SentAlert {
+            expires: None,
+            ..sent_alert
+        };
+        assert!(!is_forgotten(&undated, &(now + chrono::Duration::days(6))));
+        assert!(is_forgotten(&undated, &(now + chrono::Duration::days(7))));
+    }
+
+    #[test]
+    fn cap_alert_is_parsed() {
+        let alert = parse_cap_alert(ALERT).unwrap();
+
+        assert_eq!(alert.identifier, "2.49.0.1.578.0.20231018073011.052");
+        assert_eq!(alert.message_type, MessageType::Alert);
+        assert!(alert.references.is_empty());
+        assert_eq!(alert.severity, Severity::Moderate);
+        assert_eq!(alert.event, "rain");
+        assert_eq!(alert.headline.as_deref(), Some("Rain, yellow level"));
+        assert_eq!(alert.area, "Agder");
+        assert_eq!(
+            alert.onset,
+            Some(DateTime::parse_from_rfc3339("2023-10-18T15:00:00+00:00").unwrap())
+        );
+        assert_eq!(
+            alert.expires,
+            Some(DateTime::parse_from_rfc3339("2023-10-20T00:00:00+00:00").unwrap())
+        );
+    }
+
+    #[test]
+    fn cap_update_references_the_alert() {
+        let alert = parse_cap_alert(ALERT).unwrap();
+        let update = parse_cap_alert(UPDATE).unwrap();
+
+        assert_eq!(update.message_type, MessageType::Update);
+        assert_eq!(update.references, vec![alert.identifier]);
+        assert_eq!(update.severity, Severity::Severe);
+    }
+
+    #[test]
+    fn only_new_and_upgraded_alerts_are_sent() {
+        let alert = parse_cap_alert(ALERT).unwrap();
+        let update = parse_cap_alert(UPDATE).unwrap();
+
+        assert_eq!(alert_change(&alert, None), Some(AlertChange::New));
+        assert_eq!(
+            alert_change(&update, Some(Severity::Moderate)),
+            Some(AlertChange::Upgraded)
+        );
+        assert_eq!(alert_change(&update, Some(Severity::Severe)), None);
+        assert_eq!(alert_change(&update, Some(Severity::Extreme)), None);
+    }
+}
@@ -1,97 +1,13 @@
 use dotenv::dotenv;
-use log::{debug, error, info, warn};
+use log::error;
 use std::env;
-use std::error;
 
-use anyhow::Result;
-use reqwest::blocking::Response;
-use reqwest::StatusCode;
-use serde_json::Value;
+use weather_bot::geo::{SearchBias, YandexGeocoder};
+use weather_bot::http;
 
-#[derive(Debug)]
-pub struct GeoCoordinate {
-    latitude: f64,
-    longitude: f64,
-}
-
-impl GeoCoordinate {
-    fn new(latitude: f64, longitude: f64) -> Result<GeoCoordinate> {
-        Ok(Self {
-            latitude,
-            longitude,
-        })
-    }
-}
-
-#[derive(Debug)]
-pub enum RequestError {
-    General(String),
-    Reqwest(reqwest::Error),
-}
-
-impl std::fmt::Display for RequestError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            RequestError::General(s) => write!(f, "{}", s),
-            RequestError::Reqwest(_e) => write!(f, ""),
-        }
-    }
-}
-
-impl error::Error for RequestError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match self {
-            RequestError::General(_) => None,
-            RequestError::Reqwest(e) => Some(e),
-        }
-    }
-}
-
-fn request_api(addr: String) -> Result<Response, RequestError> {
-    let api_key = env::var("YA_GEOAPI_KEY").unwrap();
-    let api_url = format!(
-        "https://geocode-maps.yandex.ru/1.x/?apikey={}&geocode={}&format=json",
-        api_key, addr
-    );
-
-    let client = reqwest::blocking::Client::builder()
-        // .user_agent(APP_USER_AGENT)
-        .build()
-        .unwrap();
-
-    // info!("Agent: {}", APP_USER_AGENT);
-    info!("Request API: {}...", api_url);
-
-    let res = client.get(api_url).send();
-    let returned_status = res.as_ref().unwrap().status();
-    match returned_status {
-        StatusCode::OK => {
-            info!("Received response");
-            Ok(res.unwrap())
-        }
-        StatusCode::FORBIDDEN => {
-            warn!("Forbidden 403 API request");
-            let error = "Forbidden 403 API request".to_string();
-            Err(RequestError::General(error))
-        }
-        _ => {
-            let error = res.err().unwrap();
-            error!("Error API request: {}", error);
-            Err(RequestError::Reqwest(error))
-        }
-    }
-}
-
-fn parse_geo_response(value: &Value) -> Result<GeoCoordinate> {
-    let geo_point =
-        &value["response"]["GeoObjectCollection"]["featureMember"][0]["GeoObject"]["Point"]["pos"];
-    let geo_point: Vec<&str> = geo_point.as_str().unwrap().split(' ').collect();
-    let lat = geo_point[1].parse::<f64>().unwrap();
-    let lon = geo_point[0].parse::<f64>().unwrap();
-    GeoCoordinate::new(lat, lon)
-}
-
-fn main() {
+/// Prints the Yandex geocoder response to the query, `Moscow` by default
+#[tokio::main]
+async fn main() {
     let _ = dotenv().ok();
     match env::var("RUST_LOG") {
         Ok(value) => println!("RUST_LOG set to {}", value),
@@ -103,9 +19,27 @@ fn main() {
     }
     pretty_env_logger::init();
 
-    let res = request_api("Moscow".to_string()).unwrap().text().unwrap();
-    let json_value: Value = serde_json::from_str(&res).unwrap();
-
-    let coordinates = parse_geo_response(&json_value);
-    println!("{:#?}", coordinates);
+    let client = match http::build_client() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Can't build the HTTP client: {}", err);
+            return;
+        }
+    };
+    let geocoder = match YandexGeocoder::from_env(client) {
+        Ok(geocoder) => geocoder,
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+
+    let query = env::args().nth(1).unwrap_or_else(|| "Moscow".to_string());
+    match geocoder
+        .search_response(&query, &SearchBias::default())
+        .await
+    {
+        Ok(response) => println!("{:#?}", response),
+        Err(err) => error!("{}", err),
+    }
 }
@@ -0,0 +1,316 @@
+use chrono::{DateTime, FixedOffset};
+
+use crate::error::RequestError;
+use crate::geo::GeoCoordinate;
+use crate::settings::Units;
+use crate::weather::{DayForecast, Forecast, Hours, NextHours};
+
+pub fn display_next_hours_telegram(forecast: &Forecast) -> Option<String> {
+    let periods: Vec<String> = [
+        (Hours::Hour1, "Next hour"),
+        (Hours::Hour3, "next 3h"),
+        (Hours::Hour6, "next 6h"),
+        (Hours::Hour12, "next 12h"),
+    ]
+    .into_iter()
+    .filter_map(|(hours, title)| {
+        forecast
+            .get_next_hours(hours)
+            .map(|next| format!("{}: {}", title, next.symbol.describe()))
+    })
+    .collect();
+
+    if periods.is_empty() {
+        None
+    } else {
+        Some(periods.join(", "))
+    }
+}
+
+/// First lines of a forecast reply, so the user can tell which place was used:
+/// `📍 Moscow, Russia (55.75, 37.62) — 14:00 UTC+03:00` and the map link.
+/// The offset is shown, as the timezone of the place may be unknown
+pub fn display_place_header_telegram(
+    title: Option<&str>,
+    point: &GeoCoordinate,
+    local_time: &DateTime<FixedOffset>,
+) -> String {
+    let coordinates = format!("{:.2}, {:.2}", point.latitude, point.longitude);
+    let place = match title {
+        Some(title) => format!("{} ({})", title, coordinates),
+        None => coordinates,
+    };
+
+    format!(
+        "📍 {} — {}\n🗺 {}",
+        place,
+        local_time.format("%H:%M UTC%:z"),
+        point.map_url()
+    )
+}
+
+pub fn display_forecast_telegram(forecast: &Forecast, units: &Units) -> String {
+    let temperature = units.temperature;
+    let wind_speed = units.wind_speed;
+
+    let mut text = display_next_hours_telegram(forecast)
+        .map(|summary| format!("{}\n\n", summary))
+        .unwrap_or_default();
+    text += &format!(
+        "Air Pressure: {} {}\nTemperature: {:.1}{}\nHumidity: {:.0}% \nCloud: {:.0}%\nWind Direction: {:.0}°\nWind Speed: {:.1} {}",
+        units.pressure.convert(forecast.air_pressure_at_sea_level).round(),
+        units.pressure.symbol(),
+        temperature.convert(forecast.air_temperature),
+        temperature.symbol(),
+        forecast.relative_humidity,
+        forecast.cloud_area_fraction,
+        forecast.wind_from_direction,
+        wind_speed.convert(forecast.wind_speed),
+        wind_speed.symbol(),
+    );
+
+    if let (Some(low), Some(medium), Some(high)) = (
+        forecast.cloud_area_fraction_low,
+        forecast.cloud_area_fraction_medium,
+        forecast.cloud_area_fraction_high,
+    ) {
+        text.push_str(&format!(
+            "\nCloud Layers: low {:.0}%, medium {:.0}%, high {:.0}%",
+            low, medium, high
+        ));
+    }
+    if let Some(gust) = forecast.wind_speed_of_gust {
+        text.push_str(&format!(
+            "\nWind Gust: {:.1} {}",
+            wind_speed.convert(gust),
+            wind_speed.symbol()
+        ));
+    }
+    if let Some(dew_point) = forecast.dew_point_temperature {
+        text.push_str(&format!(
+            "\nDew Point: {:.1}{}",
+            temperature.convert(dew_point),
+            temperature.symbol()
+        ));
+    }
+    if let Some(fog) = forecast.fog_area_fraction.filter(|fog| *fog > 0.0) {
+        text.push_str(&format!("\nFog: {:.0}%", fog));
+    }
+    if let Some(uv_index) = forecast.ultraviolet_index_clear_sky {
+        text.push_str(&format!("\nUV Index: {:.1}", uv_index));
+    }
+    for (hours, title) in [
+        (Hours::Hour1, "next hour"),
+        (Hours::Hour3, "next 3 hours"),
+        (Hours::Hour6, "next 6 hours"),
+        (Hours::Hour12, "next 12 hours"),
+    ] {
+        if let Some(precipitation) = forecast
+            .get_next_hours(hours)
+            .and_then(display_precipitation_telegram)
+        {
+            text.push_str(&format!("\nPrecipitation {}: {}", title, precipitation));
+        }
+    }
+
+    text
+}
+
+pub fn display_precipitation_telegram(next: &NextHours) -> Option<String> {
+    let mut text = match (next.precipitation_amount, next.probability_of_precipitation) {
+        (Some(amount), _) => format!("{} mm", amount),
+        (None, Some(_)) => String::new(),
+        (None, None) => return None,
+    };
+    if let (Some(min), Some(max)) = (next.precipitation_amount_min, next.precipitation_amount_max) {
+        text.push_str(&format!(" ({}–{} mm)", min, max));
+    }
+    if let Some(probability) = next.probability_of_precipitation {
+        if !text.is_empty() {
+            text.push_str(", ");
+        }
+        text.push_str(&format!("{}% chance", probability));
+    }
+
+    Some(text)
+}
+
+pub fn display_daily_forecast_telegram(days: &[DayForecast], units: &Units) -> String {
+    let temperature = units.temperature;
+    let wind_speed = units.wind_speed;
+
+    days.iter()
+        .map(|day| {
+            let weather = day
+                .weather_code
+                .as_ref()
+                .map(|code| format!("{} {}, ", code.emoji(None), code.description()))
+                .unwrap_or_default();
+            format!(
+                "{}: {}..{}{}, {}{:.1} mm, wind up to {} {}",
+                day.date.format("%a %d %b"),
+                temperature.convert(day.min_temperature).round(),
+                temperature.convert(day.max_temperature).round(),
+                temperature.symbol(),
+                weather,
+                day.precipitation_amount,
+                wind_speed.convert(day.max_wind_speed).round(),
+                wind_speed.symbol(),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn display_error_telegram(err: &RequestError) -> String {
+    match err {
+        RequestError::NotFound(query) => {
+            format!("Can't find \"{}\". Check the name and try again", query)
+        }
+        RequestError::RateLimited => {
+            "Too many requests right now. Please try again in a minute".to_string()
+        }
+        RequestError::Upstream(_) | RequestError::Reqwest(_) => {
+            "The weather service is unavailable. Please try again later".to_string()
+        }
+        RequestError::Parse(_) => "The weather service returned unexpected data".to_string(),
+        RequestError::NoForecast(_) => "There is no forecast for this time yet".to_string(),
+        RequestError::OutOfHorizon { time, from, till } => {
+            let offset = time.offset();
+            format!(
+                "The forecast covers only {} – {}",
+                from.with_timezone(offset).format("%d %b %H:%M"),
+                till.with_timezone(offset).format("%d %b %H:%M")
+            )
+        }
+        RequestError::Config(_) => "The bot is misconfigured. Please contact the admin".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{PressureUnit, TemperatureUnit, WindSpeedUnit};
+    use crate::weather::NextHoursForecast;
+    use crate::weather_codes::WeatherSymbol;
+
+    fn next_hours(symbol_code: &str, amount: Option<f64>) -> NextHours {
+        NextHours {
+            symbol: WeatherSymbol::from(symbol_code),
+            precipitation_amount: amount,
+            precipitation_amount_min: None,
+            precipitation_amount_max: None,
+            probability_of_precipitation: None,
+        }
+    }
+
+    fn forecast(forecast_by_time: NextHoursForecast) -> Forecast {
+        Forecast {
+            air_pressure_at_sea_level: 1013.25,
+            air_temperature: 20.0,
+            cloud_area_fraction: 75.0,
+            relative_humidity: 60.0,
+            wind_from_direction: 225.0,
+            wind_speed: 10.0,
+            wind_speed_of_gust: None,
+            dew_point_temperature: None,
+            fog_area_fraction: None,
+            ultraviolet_index_clear_sky: None,
+            cloud_area_fraction_high: None,
+            cloud_area_fraction_medium: None,
+            cloud_area_fraction_low: None,
+            forecast_by_time,
+        }
+    }
+
+    #[test]
+    fn next_hours_are_summarized() {
+        let forecast = forecast(NextHoursForecast::from([
+            (Hours::Hour1, next_hours("lightrain", Some(0.4))),
+            (Hours::Hour6, next_hours("partlycloudy_day", Some(1.2))),
+        ]));
+        assert_eq!(
+            display_next_hours_telegram(&forecast).as_deref(),
+            Some("Next hour: 🌧 light rain, next 6h: ⛅ partly cloudy")
+        );
+        assert_eq!(
+            display_next_hours_telegram(&self::forecast(NextHoursForecast::new())),
+            None
+        );
+    }
+
+    #[test]
+    fn precipitation_is_shown_with_the_range_and_the_chance() {
+        let mut next = next_hours("rain", Some(1.5));
+        assert_eq!(
+            display_precipitation_telegram(&next).as_deref(),
+            Some("1.5 mm")
+        );
+
+        next.precipitation_amount_min = Some(0.5);
+        next.precipitation_amount_max = Some(3.0);
+        next.probability_of_precipitation = Some(80.0);
+        assert_eq!(
+            display_precipitation_telegram(&next).as_deref(),
+            Some("1.5 mm (0.5–3 mm), 80% chance")
+        );
+
+        // OpenWeatherMap gives only the chance
+        let mut next = next_hours("cloudy", None);
+        next.probability_of_precipitation = Some(20.0);
+        assert_eq!(
+            display_precipitation_telegram(&next).as_deref(),
+            Some("20% chance")
+        );
+        next.probability_of_precipitation = None;
+        assert_eq!(display_precipitation_telegram(&next), None);
+    }
+
+    #[test]
+    fn forecast_is_shown_in_the_units_of_the_user() {
+        let mut forecast = forecast(NextHoursForecast::from([(
+            Hours::Hour1,
+            next_hours("lightrain", Some(0.4)),
+        )]));
+        forecast.wind_speed_of_gust = Some(15.0);
+
+        assert_eq!(
+            display_forecast_telegram(&forecast, &Units::default()),
+            "Next hour: 🌧 light rain\n\n\
+             Air Pressure: 760 mmHg\nTemperature: 20.0℃\nHumidity: 60% \nCloud: 75%\n\
+             Wind Direction: 225°\nWind Speed: 10.0 m/s\nWind Gust: 15.0 m/s\n\
+             Precipitation next hour: 0.4 mm"
+        );
+
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            pressure: PressureUnit::HPa,
+            wind_speed: WindSpeedUnit::KilometersPerHour,
+        };
+        let text = display_forecast_telegram(&forecast, &units);
+        assert!(text.contains("Air Pressure: 1013 hPa"), "{}", text);
+        assert!(text.contains("Temperature: 68.0℉"), "{}", text);
+        assert!(text.contains("Wind Speed: 36.0 km/h"), "{}", text);
+        assert!(text.contains("Wind Gust: 54.0 km/h"), "{}", text);
+    }
+
+    #[test]
+    fn place_header_has_the_local_time_and_the_map_link() {
+        let point = GeoCoordinate::new(55.7558, 37.6173);
+        let local_time = DateTime::parse_from_rfc3339("2023-10-18T14:05:00+03:00").unwrap();
+        assert_eq!(
+            display_place_header_telegram(Some("Moscow, Russia"), &point, &local_time),
+            "📍 Moscow, Russia (55.76, 37.62) — 14:05 UTC+03:00\n\
+             🗺 https://www.openstreetmap.org/?mlat=55.7558&mlon=37.6173#map=12/55.7558/37.6173"
+        );
+
+        // The location sent without a found name, in a half hour timezone
+        let local_time = DateTime::parse_from_rfc3339("2023-10-18T17:30:00+05:30").unwrap();
+        let header = display_place_header_telegram(None, &point, &local_time);
+        assert!(
+            header.starts_with("📍 55.76, 37.62 — 17:30 UTC+05:30\n🗺 "),
+            "{}",
+            header
+        );
+    }
+}
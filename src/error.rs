@@ -0,0 +1,136 @@
+use std::error;
+
+use chrono::{DateTime, FixedOffset};
+use reqwest::StatusCode;
+
+/// Errors of the geocoding and forecast requests
+#[derive(Debug)]
+pub enum RequestError {
+    /// Nothing was found for the query
+    NotFound(String),
+    /// Upstream API answered with an unexpected HTTP status
+    Upstream(StatusCode),
+    /// Upstream API answered with a body we can't understand
+    Parse(String),
+    /// Upstream API asks to slow down
+    RateLimited,
+    /// Required configuration is missing or invalid
+    Config(String),
+    /// There is no forecast for the requested time
+    NoForecast(DateTime<FixedOffset>),
+    /// The requested time is outside of the forecast timeseries
+    OutOfHorizon {
+        time: DateTime<FixedOffset>,
+        from: DateTime<FixedOffset>,
+        till: DateTime<FixedOffset>,
+    },
+    /// Network level failure
+    Reqwest(reqwest::Error),
+}
+
+impl RequestError {
+    /// Map the status of an upstream response to an error
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => RequestError::RateLimited,
+            status => RequestError::Upstream(status),
+        }
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::NotFound(s) => write!(f, "Nothing found for \"{}\"", s),
+            RequestError::Upstream(status) => write!(f, "Upstream API returned {}", status),
+            RequestError::Parse(s) => write!(f, "Can't parse API response: {}", s),
+            RequestError::RateLimited => write!(f, "Upstream API rate limit exceeded"),
+            RequestError::Config(s) => write!(f, "Configuration error: {}", s),
+            RequestError::NoForecast(time) => write!(f, "No forecast for {}", time),
+            RequestError::OutOfHorizon { time, from, till } => write!(
+                f,
+                "{} is outside of the forecast horizon {}..{}",
+                time, from, till
+            ),
+            RequestError::Reqwest(e) => write!(f, "Request failed: {}", e),
+        }
+    }
+}
+
+impl error::Error for RequestError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RequestError::Reqwest(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => RequestError::from_status(status),
+            // The URL may have the API key in the query
+            None => RequestError::Reqwest(err.without_url()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RequestError {
+    fn from(err: serde_json::Error) -> Self {
+        RequestError::Parse(err.to_string())
+    }
+}
+
+impl From<quick_xml::DeError> for RequestError {
+    fn from(err: quick_xml::DeError) -> Self {
+        RequestError::Parse(err.to_string())
+    }
+}
+
+/// Errors of the bot state storage
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    Serde(serde_json::Error),
+    /// A thread panicked while holding the storage lock
+    Poisoned,
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "Storage IO error: {}", e),
+            StorageError::Serde(e) => write!(f, "Storage format error: {}", e),
+            StorageError::Poisoned => write!(f, "Storage lock is poisoned"),
+        }
+    }
+}
+
+impl error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StorageError::Io(e) => Some(e),
+            StorageError::Serde(e) => Some(e),
+            StorageError::Poisoned => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(err: std::io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(err: serde_json::Error) -> Self {
+        StorageError::Serde(err)
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for StorageError {
+    fn from(_: std::sync::PoisonError<T>) -> Self {
+        StorageError::Poisoned
+    }
+}
@@ -1,92 +1,744 @@
-use log::{debug, error, info, warn};
+use log::{error, info, warn};
 use std::env;
+use std::sync::Arc;
 
-use anyhow::Result;
-use serde_json::Value;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 
-static APP_USER_AGENT: &str = concat!(
-    env!("CARGO_PKG_NAME"),
-    "/",
-    env!("CARGO_PKG_VERSION"),
-    " ",
-    env!("CARGO_PKG_REPOSITORY")
-);
+use crate::error::RequestError;
+use crate::geonames::GeoNames;
+use crate::http::HttpClient;
+use crate::nominatim::Nominatim;
+use crate::yandex::{self, GeocoderResponse};
 
-#[derive(Debug)]
+/// Mean radius of the Earth
+const EARTH_RADIUS_KM: f64 = 6371.0;
+/// How many candidates are requested from the geocoders
+pub const MAX_CANDIDATES: usize = 5;
+const YANDEX_GEOCODER_URL: &str = "https://geocode-maps.yandex.ru/1.x/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GeoCoordinate {
     pub latitude: f64,
     pub longitude: f64,
 }
 
 impl GeoCoordinate {
-    fn new(latitude: f64, longitude: f64) -> Result<GeoCoordinate> {
-        Ok(Self {
+    pub fn new(latitude: f64, longitude: f64) -> GeoCoordinate {
+        Self {
             latitude,
             longitude,
-        })
-    }
-}
-
-// #[derive(Debug)]
-// pub enum RequestError {
-//     General(String),
-//     Reqwest(reqwest::Error),
-// }
-
-// impl std::fmt::Display for RequestError {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         match self {
-//             RequestError::General(s) => write!(f, "{}", s),
-//             RequestError::Reqwest(_e) => write!(f, ""),
-//         }
-//     }
-// }
-
-// impl error::Error for RequestError {
-//     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-//         match self {
-//             RequestError::General(_) => None,
-//             RequestError::Reqwest(e) => Some(e),
-//         }
-//     }
-// }
-
-pub async fn request_geo_api(addr: String) -> anyhow::Result<GeoCoordinate> {
-    let api_key = env::var("YA_GEOAPI_KEY").expect("YA_GEOAPI_KEY must be set up");
-    let api_url = format!(
-        "https://geocode-maps.yandex.ru/1.x/?apikey={}&geocode={}&format=json",
-        api_key, addr
-    );
-
-    let client = reqwest::Client::builder()
-        .user_agent(APP_USER_AGENT)
-        .build()
-        .unwrap();
+        }
+    }
+
+    /// Great-circle distance by the haversine formula
+    pub fn distance_km(&self, other: &GeoCoordinate) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+
+    /// Coordinates typed by the user, e.g. `55.75, 37.62` or `55.75 37.62`,
+    /// the latitude goes first
+    pub fn parse(text: &str) -> Option<GeoCoordinate> {
+        let (lat, lon) = text
+            .trim()
+            .split_once(',')
+            .or_else(|| text.trim().split_once(' '))?;
+        let latitude = lat.trim().parse::<f64>().ok()?;
+        let longitude = lon.trim().parse::<f64>().ok()?;
+
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+            .then(|| GeoCoordinate::new(latitude, longitude))
+    }
+
+    /// OpenStreetMap link with a marker at the point
+    pub fn map_url(&self) -> String {
+        format!(
+            "https://www.openstreetmap.org/?mlat={lat:.4}&mlon={lon:.4}#map=12/{lat:.4}/{lon:.4}",
+            lat = self.latitude,
+            lon = self.longitude
+        )
+    }
+}
+
+/// Found object with its address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Place {
+    pub name: String,
+    /// Region and country, e.g. `Illinois, United States of America`
+    pub description: Option<String>,
+    /// Full formatted address
+    pub address: Option<String>,
+    /// ISO 3166-1 alpha-2 code
+    pub country_code: Option<String>,
+    /// Type of the object, e.g. `locality` or `street`
+    pub kind: Option<String>,
+    pub coordinates: GeoCoordinate,
+}
+
+impl Place {
+    /// Short name for the headers, e.g. `Moscow, Russia`
+    pub fn title(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{}, {}", self.name, description),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Text to tell the place from the others with the same name
+    pub fn label(&self) -> String {
+        match (&self.address, &self.description) {
+            (Some(address), _) => address.clone(),
+            (None, Some(description)) => format!("{}, {}", self.name, description),
+            (None, None) => self.name.clone(),
+        }
+    }
+}
+
+/// Area to prefer or to search in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchArea {
+    /// Lower left corner
+    pub lower: GeoCoordinate,
+    /// Upper right corner
+    pub upper: GeoCoordinate,
+}
 
-    info!("Agent: {}", APP_USER_AGENT);
-    info!("Request API: {}...", api_url);
+impl SearchArea {
+    /// Area of the size in degrees around the point
+    pub fn around(center: GeoCoordinate, latitude_span: f64, longitude_span: f64) -> Self {
+        Self {
+            lower: GeoCoordinate::new(
+                center.latitude - latitude_span / 2.0,
+                center.longitude - longitude_span / 2.0,
+            ),
+            upper: GeoCoordinate::new(
+                center.latitude + latitude_span / 2.0,
+                center.longitude + longitude_span / 2.0,
+            ),
+        }
+    }
 
-    let res = client.get(api_url).send().await.unwrap();
+    pub fn center(&self) -> GeoCoordinate {
+        GeoCoordinate::new(
+            (self.lower.latitude + self.upper.latitude) / 2.0,
+            (self.lower.longitude + self.upper.longitude) / 2.0,
+        )
+    }
 
-    match res.error_for_status() {
-        Ok(res) => {
-            let json_value: Value =
-                serde_json::from_str(res.text().await.as_ref().unwrap()).unwrap();
-            let geo_point = parse_geo_response(&json_value).unwrap();
-            Ok(geo_point)
+    pub fn contains(&self, point: &GeoCoordinate) -> bool {
+        (self.lower.latitude..=self.upper.latitude).contains(&point.latitude)
+            && (self.lower.longitude..=self.upper.longitude).contains(&point.longitude)
+    }
+}
+
+/// Hints for the geocoders which support them, e.g. the language of the
+/// user and the region of the home
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchBias {
+    /// ISO 639-1 code of the language of the names, e.g. `ru`
+    pub language: Option<String>,
+    /// The places in the area are preferred
+    pub area: Option<SearchArea>,
+    /// Search only in the area
+    pub restrict_to_area: bool,
+}
+
+/// Size of the grid cells the biased searches are cached by
+const AREA_CELL_DEGREES: f64 = 5.0;
+
+impl SearchBias {
+    /// The names depend on the language and the found places on the area.
+    /// The area is rounded to the grid cell of its center, so the neighbours
+    /// share the entry instead of caching the city for every home
+    pub fn cache_key(&self, query: &str) -> String {
+        let mut key = String::new();
+        if let Some(language) = &self.language {
+            key.push_str(&format!("lang={}|", language));
+        }
+        if let Some(area) = &self.area {
+            let center = area.center();
+            let cell = |degrees: f64| (degrees / AREA_CELL_DEGREES).floor() * AREA_CELL_DEGREES;
+            key.push_str(&format!(
+                "area={},{}|",
+                cell(center.latitude),
+                cell(center.longitude)
+            ));
         }
+        if self.restrict_to_area {
+            key.push_str("restrict|");
+        }
+        key + query
+    }
+}
+
+/// Forward and reverse geocoding backend
+#[async_trait]
+pub trait Geocoder: Send + Sync {
+    /// Short name for the logs and the config
+    fn name(&self) -> &'static str;
+
+    /// Find the places matching the query, the best match goes first.
+    /// Nothing found is a `NotFound` error
+    async fn search(&self, query: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError>;
+
+    /// Find the coordinates of the best match
+    async fn geocode(&self, query: &str, bias: &SearchBias) -> Result<GeoCoordinate, RequestError> {
+        let places = self.search(query, bias).await?;
+        places
+            .first()
+            .map(|place| place.coordinates)
+            .ok_or_else(|| RequestError::NotFound(query.to_string()))
+    }
+
+    /// Find the name of the locality at the point
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError>;
+}
+
+fn point_not_found(point: &GeoCoordinate) -> RequestError {
+    RequestError::NotFound(format!("{:.4}, {:.4}", point.latitude, point.longitude))
+}
+
+/// Place at the point named after the locality, the coordinates are the
+/// name when no geocoder knows it
+pub async fn place_at(geocoder: &dyn Geocoder, point: GeoCoordinate) -> Place {
+    let name = match geocoder.reverse(&point).await {
+        Ok(name) => name,
         Err(err) => {
-            error!("Error: {}", err.status().unwrap());
-            Err(err.into())
+            warn!("Can't find the name of {:?}: {}", point, err);
+            format!("{:.4}, {:.4}", point.latitude, point.longitude)
+        }
+    };
+
+    Place {
+        name,
+        description: None,
+        address: None,
+        country_code: None,
+        kind: None,
+        coordinates: point,
+    }
+}
+
+/// Geocoder backed by the Yandex geocoder API
+#[derive(Debug, Clone)]
+pub struct YandexGeocoder {
+    client: HttpClient,
+    api_key: String,
+}
+
+impl YandexGeocoder {
+    pub fn new(client: HttpClient, api_key: String) -> Self {
+        Self { client, api_key }
+    }
+
+    pub fn from_env(client: HttpClient) -> Result<Self, RequestError> {
+        let api_key = env::var("YA_GEOAPI_KEY")
+            .map_err(|_| RequestError::Config("YA_GEOAPI_KEY must be set up".to_string()))?;
+        Ok(Self::new(client, api_key))
+    }
+
+    async fn request_geo_api(
+        &self,
+        params: &[(&str, String)],
+    ) -> Result<GeocoderResponse, RequestError> {
+        // The key is not logged
+        info!("Request API: {} {:?}...", YANDEX_GEOCODER_URL, params);
+
+        let request = self
+            .client
+            .get(YANDEX_GEOCODER_URL)
+            .query(&[("apikey", &self.api_key)])
+            .query(params);
+        let res = self.client.send(request).await?;
+        let status = res.status();
+
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        Ok(serde_json::from_str(&res.text().await?)?)
+    }
+
+    /// The whole response to the query, with the bounds, precision
+    /// and address components the places don't keep
+    pub async fn search_response(
+        &self,
+        query: &str,
+        bias: &SearchBias,
+    ) -> Result<GeocoderResponse, RequestError> {
+        let params = yandex_params(query, None, MAX_CANDIDATES, bias);
+        self.request_geo_api(&params).await
+    }
+}
+
+#[async_trait]
+impl Geocoder for YandexGeocoder {
+    fn name(&self) -> &'static str {
+        "yandex"
+    }
+
+    async fn search(&self, query: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError> {
+        parse_geo_response(self.search_response(query, bias).await?)
+    }
+
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError> {
+        // Yandex expects the longitude first
+        let geocode = format!("{},{}", point.longitude, point.latitude);
+        let params = yandex_params(&geocode, Some("locality"), 1, &SearchBias::default());
+        parse_reverse_response(self.request_geo_api(&params).await?)
+            .ok_or_else(|| point_not_found(point))
+    }
+}
+
+/// Yandex locale of the ISO 639-1 language, the other languages are not supported
+fn yandex_lang(language: &str) -> Option<&'static str> {
+    match language {
+        "ru" => Some("ru_RU"),
+        "uk" => Some("uk_UA"),
+        "be" => Some("be_BY"),
+        "tr" => Some("tr_TR"),
+        "en" => Some("en_US"),
+        _ => None,
+    }
+}
+
+/// Query parameters of the Yandex geocoder, see
+/// https://yandex.com/maps-api/docs/geocoder-api/request.html
+fn yandex_params(
+    geocode: &str,
+    kind: Option<&str>,
+    results: usize,
+    bias: &SearchBias,
+) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("geocode", geocode.to_string()),
+        ("format", "json".to_string()),
+        ("results", results.to_string()),
+    ];
+    if let Some(kind) = kind {
+        params.push(("kind", kind.to_string()));
+    }
+    if let Some(lang) = bias.language.as_deref().and_then(yandex_lang) {
+        params.push(("lang", lang.to_string()));
+    }
+    // Yandex expects the longitude first everywhere. The results are
+    // preferred in the area of `ll` and `spn` and limited to `bbox` by `rspn`
+    match bias.area {
+        Some(area) if bias.restrict_to_area => {
+            params.push((
+                "bbox",
+                format!(
+                    "{},{}~{},{}",
+                    area.lower.longitude,
+                    area.lower.latitude,
+                    area.upper.longitude,
+                    area.upper.latitude
+                ),
+            ));
+            params.push(("rspn", "1".to_string()));
+        }
+        Some(area) => {
+            let center = area.center();
+            params.push(("ll", format!("{},{}", center.longitude, center.latitude)));
+            params.push((
+                "spn",
+                format!(
+                    "{},{}",
+                    area.upper.longitude - area.lower.longitude,
+                    area.upper.latitude - area.lower.latitude
+                ),
+            ));
+        }
+        None => {}
+    }
+
+    params
+}
+
+impl From<yandex::Position> for GeoCoordinate {
+    fn from(pos: yandex::Position) -> Self {
+        GeoCoordinate::new(pos.latitude, pos.longitude)
+    }
+}
+
+impl From<yandex::GeoObject> for Place {
+    fn from(geo_object: yandex::GeoObject) -> Self {
+        let meta_data = geo_object.meta_data_property.geocoder_meta_data;
+        Place {
+            name: geo_object.name,
+            description: geo_object.description,
+            address: meta_data.text,
+            country_code: meta_data.address.and_then(|address| address.country_code),
+            kind: meta_data.kind,
+            coordinates: geo_object.point.pos.into(),
+        }
+    }
+}
+
+/// Returns the found places, `NotFound` when there are none
+pub fn parse_geo_response(response: GeocoderResponse) -> Result<Vec<Place>, RequestError> {
+    let collection = response.response.geo_object_collection;
+    let meta_data = collection.meta_data_property.geocoder_response_meta_data;
+    if meta_data.found == 0 || collection.feature_member.is_empty() {
+        return Err(RequestError::NotFound(meta_data.request));
+    }
+
+    Ok(collection
+        .feature_member
+        .into_iter()
+        .map(|member| member.geo_object.into())
+        .collect())
+}
+
+/// Returns the name of the first found object or `None` when nothing was found
+pub fn parse_reverse_response(response: GeocoderResponse) -> Option<String> {
+    response
+        .response
+        .geo_object_collection
+        .feature_member
+        .into_iter()
+        .next()
+        .map(|member| member.geo_object.name)
+}
+
+/// Tries the geocoders in order until one of them succeeds
+pub struct FallbackGeocoder {
+    geocoders: Vec<Arc<dyn Geocoder>>,
+}
+
+impl FallbackGeocoder {
+    pub fn new(geocoders: Vec<Arc<dyn Geocoder>>) -> Self {
+        Self { geocoders }
+    }
+}
+
+#[async_trait]
+impl Geocoder for FallbackGeocoder {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    async fn search(&self, query: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError> {
+        let mut last_error = RequestError::NotFound(query.to_string());
+        for geocoder in &self.geocoders {
+            match geocoder.search(query, bias).await {
+                Ok(places) => return Ok(places),
+                Err(err) => {
+                    warn!(
+                        "Geocoder {} failed for \"{}\": {}",
+                        geocoder.name(),
+                        query,
+                        err
+                    );
+                    last_error = err;
+                }
+            }
         }
+        Err(last_error)
+    }
+
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError> {
+        let mut last_error = point_not_found(point);
+        for geocoder in &self.geocoders {
+            match geocoder.reverse(point).await {
+                Ok(name) => return Ok(name),
+                Err(err) => {
+                    warn!(
+                        "Geocoder {} failed for {:?}: {}",
+                        geocoder.name(),
+                        point,
+                        err
+                    );
+                    last_error = err;
+                }
+            }
+        }
+        Err(last_error)
     }
 }
 
-pub fn parse_geo_response(value: &Value) -> Result<GeoCoordinate> {
-    let geo_point =
-        &value["response"]["GeoObjectCollection"]["featureMember"][0]["GeoObject"]["Point"]["pos"];
-    let geo_point: Vec<&str> = geo_point.as_str().unwrap().split(' ').collect();
-    let lat = geo_point[1].parse::<f64>().unwrap();
-    let lon = geo_point[0].parse::<f64>().unwrap();
-    GeoCoordinate::new(lat, lon)
+/// Build the geocoder chain from `GEOCODERS`, e.g. `yandex,nominatim,geonames`.
+/// The `geonames` geocoder needs the dump loaded from `GEONAMES_FILE`
+pub fn geocoder_from_env(
+    client: HttpClient,
+    geonames: Option<Arc<GeoNames>>,
+) -> Result<Arc<dyn Geocoder>, RequestError> {
+    let names = env::var("GEOCODERS").unwrap_or_else(|_| "yandex".to_string());
+
+    let mut geocoders: Vec<Arc<dyn Geocoder>> = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let geocoder: Arc<dyn Geocoder> = match name.to_lowercase().as_str() {
+            "yandex" => Arc::new(YandexGeocoder::from_env(client.clone())?),
+            "nominatim" => Arc::new(Nominatim::new(client.clone())),
+            "geonames" => geonames
+                .clone()
+                .ok_or_else(|| RequestError::Config("GEONAMES_FILE must be set up".to_string()))?,
+            _ => {
+                return Err(RequestError::Config(format!(
+                    "unknown geocoder \"{}\" in GEOCODERS",
+                    name
+                )))
+            }
+        };
+        info!("Use the geocoder: {}", geocoder.name());
+        geocoders.push(geocoder);
+    }
+
+    match geocoders.len() {
+        0 => Err(RequestError::Config("GEOCODERS is empty".to_string())),
+        1 => Ok(geocoders.remove(0)),
+        _ => Ok(Arc::new(FallbackGeocoder::new(geocoders))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Geocoder which always fails with the same error
+    struct Failing;
+
+    #[async_trait]
+    impl Geocoder for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn search(
+            &self,
+            _query: &str,
+            _bias: &SearchBias,
+        ) -> Result<Vec<Place>, RequestError> {
+            Err(RequestError::RateLimited)
+        }
+
+        async fn reverse(&self, _point: &GeoCoordinate) -> Result<String, RequestError> {
+            Err(RequestError::RateLimited)
+        }
+    }
+
+    #[test]
+    fn distance_between_cities() {
+        let moscow = GeoCoordinate::new(55.7558, 37.6173);
+        let saint_petersburg = GeoCoordinate::new(59.9386, 30.3141);
+
+        let distance = moscow.distance_km(&saint_petersburg);
+        assert!((distance - 634.0).abs() < 5.0, "{}", distance);
+        assert_eq!(moscow.distance_km(&moscow), 0.0);
+    }
+
+    #[test]
+    fn yandex_response_is_parsed() {
+        let response: GeocoderResponse =
+            serde_json::from_str(include_str!("../tests/fixtures/yandex/springfield.json"))
+                .unwrap();
+        let collection = &response.response.geo_object_collection;
+        assert_eq!(
+            collection
+                .meta_data_property
+                .geocoder_response_meta_data
+                .found,
+            3
+        );
+        let geo_object = &collection.feature_member[0].geo_object;
+        let meta_data = &geo_object.meta_data_property.geocoder_meta_data;
+        assert_eq!(meta_data.precision.as_deref(), Some("other"));
+        assert_eq!(meta_data.address.as_ref().unwrap().components.len(), 3);
+        let envelope = &geo_object.bounded_by.as_ref().unwrap().envelope;
+        assert_eq!(
+            GeoCoordinate::from(envelope.lower_corner),
+            GeoCoordinate::new(39.680655, -89.765232)
+        );
+
+        let places = parse_geo_response(response).unwrap();
+
+        assert_eq!(places.len(), 3);
+        assert_eq!(places[0].name, "Springfield");
+        assert_eq!(
+            places[0].description.as_deref(),
+            Some("Illinois, United States of America")
+        );
+        assert_eq!(places[0].kind.as_deref(), Some("locality"));
+        assert_eq!(places[0].country_code.as_deref(), Some("US"));
+        assert_eq!(
+            places[0].coordinates,
+            GeoCoordinate::new(39.781721, -89.650373)
+        );
+        assert_eq!(
+            places[1].label(),
+            "United States of America, Missouri, Springfield"
+        );
+    }
+
+    #[test]
+    fn place_title_and_map_url() {
+        let response =
+            serde_json::from_str(include_str!("../tests/fixtures/yandex/springfield.json"))
+                .unwrap();
+        let place = &parse_geo_response(response).unwrap()[0];
+
+        assert_eq!(
+            place.title(),
+            "Springfield, Illinois, United States of America"
+        );
+        assert_eq!(
+            place.coordinates.map_url(),
+            "https://www.openstreetmap.org/?mlat=39.7817&mlon=-89.6504#map=12/39.7817/-89.6504"
+        );
+    }
+
+    #[test]
+    fn yandex_query_is_encoded() {
+        let bias = SearchBias {
+            language: Some("ru".to_string()),
+            area: Some(SearchArea::around(GeoCoordinate::new(55.5, 37.5), 5.0, 5.0)),
+            restrict_to_area: false,
+        };
+        let params = yandex_params("Ростов & Co #1", None, 5, &bias);
+        let request = reqwest::Client::new()
+            .get(YANDEX_GEOCODER_URL)
+            .query(&params)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.url().query(),
+            Some(
+                "geocode=%D0%A0%D0%BE%D1%81%D1%82%D0%BE%D0%B2+%26+Co+%231&format=json\
+                &results=5&lang=ru_RU&ll=37.5%2C55.5&spn=5%2C5"
+            )
+        );
+    }
+
+    #[test]
+    fn reverse_query_has_the_kind_and_no_bias() {
+        let params = yandex_params("37.62,55.75", Some("locality"), 1, &SearchBias::default());
+        assert!(params.contains(&("kind", "locality".to_string())));
+        assert!(params.contains(&("results", "1".to_string())));
+        assert!(!params
+            .iter()
+            .any(|(name, _)| *name == "lang" || *name == "ll"));
+
+        let bias = SearchBias {
+            language: Some("fr".to_string()),
+            area: Some(SearchArea {
+                lower: GeoCoordinate::new(55.0, 37.0),
+                upper: GeoCoordinate::new(56.0, 38.0),
+            }),
+            restrict_to_area: true,
+        };
+        let params = yandex_params("Moscow", None, 5, &bias);
+        assert!(params.contains(&("bbox", "37,55~38,56".to_string())));
+        assert!(params.contains(&("rspn", "1".to_string())));
+        assert!(!params
+            .iter()
+            .any(|(name, _)| *name == "lang" || *name == "ll"));
+    }
+
+    #[test]
+    fn cache_keys_depend_on_the_language_and_the_area() {
+        assert_eq!(SearchBias::default().cache_key("moscow"), "moscow");
+        let bias = SearchBias {
+            language: Some("ru".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(bias.cache_key("moscow"), "lang=ru|moscow");
+        let bias = SearchBias {
+            area: Some(SearchArea::around(
+                GeoCoordinate::new(55.75, 37.62),
+                5.0,
+                5.0,
+            )),
+            ..bias
+        };
+        assert_eq!(bias.cache_key("moscow"), "lang=ru|area=55,35|moscow");
+        let near = SearchBias {
+            area: Some(SearchArea::around(GeoCoordinate::new(56.3, 38.1), 5.0, 5.0)),
+            ..bias.clone()
+        };
+        assert_eq!(near.cache_key("moscow"), bias.cache_key("moscow"));
+
+        let toronto = SearchBias {
+            area: Some(SearchArea::around(
+                GeoCoordinate::new(43.65, -79.38),
+                5.0,
+                5.0,
+            )),
+            ..Default::default()
+        };
+        assert_eq!(toronto.cache_key("london"), "area=40,-80|london");
+    }
+
+    #[test]
+    fn empty_yandex_response_is_not_found() {
+        let response: GeocoderResponse = serde_json::from_str(
+            r#"{"response":{"GeoObjectCollection":{"metaDataProperty":{"GeocoderResponseMetaData":
+                {"request":"Atlantis","results":"5","found":"0"}},"featureMember":[]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_reverse_response(response.clone()), None);
+        assert!(matches!(
+            parse_geo_response(response),
+            Err(RequestError::NotFound(query)) if query == "Atlantis"
+        ));
+
+        // Broken positions are parse errors, not panics
+        let response = include_str!("../tests/fixtures/yandex/springfield.json")
+            .replace("-89.650373 39.781721", "-89.650373");
+        assert!(serde_json::from_str::<GeocoderResponse>(&response).is_err());
+        assert!(serde_json::from_str::<GeocoderResponse>("null").is_err());
+    }
+
+    #[test]
+    fn coordinates_are_parsed() {
+        let point = GeoCoordinate::new(55.75, 37.62);
+        assert_eq!(GeoCoordinate::parse("55.75, 37.62"), Some(point));
+        assert_eq!(GeoCoordinate::parse(" 55.75 37.62 "), Some(point));
+        assert_eq!(
+            GeoCoordinate::parse("-33.87,151.21"),
+            Some(GeoCoordinate::new(-33.87, 151.21))
+        );
+        assert_eq!(GeoCoordinate::parse("95.0, 37.62"), None);
+        assert_eq!(GeoCoordinate::parse("New York"), None);
+        assert_eq!(GeoCoordinate::parse("London, CA"), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_point_is_named_by_coordinates() {
+        let geonames =
+            GeoNames::parse(include_str!("../tests/fixtures/geonames/cities.txt")).unwrap();
+
+        let oslo = place_at(&geonames, GeoCoordinate::new(59.95, 10.8)).await;
+        assert_eq!(oslo.name, "Oslo");
+        let ocean = place_at(&geonames, GeoCoordinate::new(0.0, -30.0)).await;
+        assert_eq!(ocean.name, "0.0000, -30.0000");
+    }
+
+    #[tokio::test]
+    async fn fallback_tries_the_next_geocoder() {
+        let geonames =
+            GeoNames::parse(include_str!("../tests/fixtures/geonames/cities.txt")).unwrap();
+        let geocoder = FallbackGeocoder::new(vec![Arc::new(Failing), Arc::new(geonames)]);
+
+        let oslo = geocoder
+            .geocode("Oslo", &SearchBias::default())
+            .await
+            .unwrap();
+        assert_eq!(oslo, GeoCoordinate::new(59.91273, 10.74609));
+        assert_eq!(geocoder.reverse(&oslo).await.unwrap(), "Oslo");
+        assert!(matches!(
+            geocoder.geocode("Atlantis", &SearchBias::default()).await,
+            Err(RequestError::NotFound(_))
+        ));
+    }
 }
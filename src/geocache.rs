@@ -0,0 +1,241 @@
+use log::{debug, error};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RequestError, StorageError};
+use crate::geo::{GeoCoordinate, Geocoder, Place, SearchBias};
+use crate::storage::Storage;
+
+pub type GeoCacheStorage = Arc<dyn Storage<String, CachedPlaces>>;
+
+/// Cities don't move, so the results are kept for a long time by default
+pub const DEFAULT_TTL_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedPlaces {
+    pub places: Vec<Place>,
+    pub cached_at: DateTime<Utc>,
+}
+
+/// Counters of the cache lookups since the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Geocoder which remembers the found places of the queries
+pub struct CachedGeocoder {
+    geocoder: Arc<dyn Geocoder>,
+    storage: GeoCacheStorage,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Queries differing only in case and spaces share the cache entry
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl CachedGeocoder {
+    pub fn new(geocoder: Arc<dyn Geocoder>, storage: GeoCacheStorage, ttl: Duration) -> Self {
+        Self {
+            geocoder,
+            storage,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Expired results are misses, they are purged on the next write
+    fn lookup(&self, key: &str, now: &DateTime<Utc>) -> Option<Vec<Place>> {
+        match self.storage.get(&key.to_string()) {
+            Ok(Some(cached)) if *now - cached.cached_at < self.ttl => Some(cached.places),
+            Ok(_) => None,
+            Err(err) => {
+                error!("Can't load the geocoding result: {}", err);
+                None
+            }
+        }
+    }
+
+    pub fn stats(&self) -> GeoCacheStats {
+        GeoCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.storage.all().map(|all| all.len()).unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for CachedGeocoder {
+    fn name(&self) -> &'static str {
+        self.geocoder.name()
+    }
+
+    async fn search(&self, addr: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError> {
+        let key = bias.cache_key(&normalize_query(addr));
+        let now = Utc::now();
+
+        if let Some(mut places) = self.lookup(&key, &now) {
+            debug!("Geocoding result for \"{}\" is served from the cache", key);
+            // The entry is shared by the grid cell, the places in the area go first
+            if let Some(area) = &bias.area {
+                places.sort_by_key(|place| !area.contains(&place.coordinates));
+            }
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(places);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let places = self.geocoder.search(addr, bias).await?;
+        let cached = CachedPlaces {
+            places: places.clone(),
+            cached_at: now,
+        };
+        // The file storage writes the whole file, which blocks
+        let storage = self.storage.clone();
+        let ttl = self.ttl;
+        match tokio::task::spawn_blocking(move || store(&storage, ttl, key, cached)).await {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => error!("Can't save the geocoding result: {}", err),
+            Err(err) => error!("Can't save the geocoding result: {}", err),
+        }
+
+        Ok(places)
+    }
+
+    /// Points are rarely the same, so they are not cached
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError> {
+        self.geocoder.reverse(point).await
+    }
+}
+
+/// Save the result and purge the expired ones, so the storage doesn't grow
+/// with the queries never repeated
+fn store(
+    storage: &GeoCacheStorage,
+    ttl: Duration,
+    key: String,
+    cached: CachedPlaces,
+) -> Result<(), StorageError> {
+    let now = cached.cached_at;
+    let expired = storage.retain(&|_, cached| now - cached.cached_at < ttl)?;
+    if expired > 0 {
+        debug!("Purged {} expired geocoding results", expired);
+    }
+    storage.set(key, cached)
+}
+
+impl GeoCacheStats {
+    /// Share of the lookups served from the cache
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 / total as f64)
+    }
+}
+
+impl std::fmt::Display for GeoCacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hit_rate = match self.hit_rate() {
+            Some(hit_rate) => format!("{:.1}%", hit_rate * 100.0),
+            None => "no requests".to_string(),
+        };
+        write!(
+            f,
+            "Geocoding cache\nEntries: {}\nHits: {}\nMisses: {}\nHit rate: {}",
+            self.entries, self.hits, self.misses, hit_rate
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::YandexGeocoder;
+    use crate::http::HttpClient;
+    use crate::storage::MemoryStorage;
+
+    #[test]
+    fn queries_are_normalized() {
+        assert_eq!(normalize_query("  New   York "), "new york");
+        assert_eq!(normalize_query("МОСКВА"), "москва");
+    }
+
+    #[test]
+    fn expired_results_are_not_served() {
+        let storage: GeoCacheStorage = Arc::new(MemoryStorage::new());
+        let geocoder = CachedGeocoder::new(
+            Arc::new(YandexGeocoder::new(
+                HttpClient::new(reqwest::Client::new()),
+                String::new(),
+            )),
+            storage.clone(),
+            Duration::days(1),
+        );
+        let now = Utc::now();
+        let cached_at = now - Duration::hours(2);
+        let places = vec![Place {
+            name: "Moscow".to_string(),
+            description: Some("Russia".to_string()),
+            address: None,
+            country_code: Some("RU".to_string()),
+            kind: Some("locality".to_string()),
+            coordinates: GeoCoordinate::new(55.75, 37.62),
+        }];
+        storage
+            .set(
+                "moscow".to_string(),
+                CachedPlaces {
+                    places: places.clone(),
+                    cached_at,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(geocoder.lookup("moscow", &now), Some(places.clone()));
+        let later = now + Duration::days(1);
+        assert_eq!(geocoder.lookup("moscow", &later), None);
+
+        // Purged on the next write
+        let cached = CachedPlaces {
+            places,
+            cached_at: later,
+        };
+        store(&storage, geocoder.ttl, "london".to_string(), cached).unwrap();
+        assert_eq!(storage.get(&"moscow".to_string()).unwrap(), None);
+        assert_eq!(storage.all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn hit_rate_is_computed() {
+        let stats = GeoCacheStats {
+            hits: 3,
+            misses: 1,
+            entries: 2,
+        };
+        assert_eq!(stats.hit_rate(), Some(0.75));
+        assert!(stats.to_string().contains("Hit rate: 75.0%"));
+        assert_eq!(
+            GeoCacheStats {
+                hits: 0,
+                misses: 0,
+                entries: 0
+            }
+            .hit_rate(),
+            None
+        );
+    }
+}
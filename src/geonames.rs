@@ -0,0 +1,270 @@
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+
+use async_trait::async_trait;
+
+use crate::error::RequestError;
+use crate::geo::{GeoCoordinate, Geocoder, Place, SearchBias, MAX_CANDIDATES};
+use crate::geocache::normalize_query;
+
+/// Columns of the GeoNames dump, see https://download.geonames.org/export/dump/
+const COLUMNS: usize = 19;
+/// A point farther than this from any city has no name
+const MAX_REVERSE_DISTANCE_KM: f64 = 50.0;
+
+/// City of the GeoNames dump
+#[derive(Debug, Clone, PartialEq)]
+pub struct City {
+    pub name: String,
+    pub country_code: String,
+    /// Code of the first level division, e.g. the state in the US
+    pub admin1_code: String,
+    pub coordinates: GeoCoordinate,
+    pub population: u64,
+    /// IANA timezone name
+    pub timezone: String,
+}
+
+impl City {
+    /// The dump has no addresses, so the division and the country codes
+    /// tell the cities apart, e.g. `IL, US`
+    pub fn to_place(&self) -> Place {
+        let description = if self.admin1_code.is_empty() {
+            self.country_code.clone()
+        } else {
+            format!("{}, {}", self.admin1_code, self.country_code)
+        };
+
+        Place {
+            name: self.name.clone(),
+            description: Some(description),
+            address: None,
+            country_code: Some(self.country_code.clone()),
+            kind: Some("locality".to_string()),
+            coordinates: self.coordinates,
+        }
+    }
+}
+
+/// Offline geocoder over a GeoNames `cities15000.txt` dump
+#[derive(Debug, Default)]
+pub struct GeoNames {
+    cities: Vec<City>,
+    /// Normalized names and alternate names to the city indexes,
+    /// the most populated city goes first
+    index: HashMap<String, Vec<usize>>,
+}
+
+impl GeoNames {
+    pub fn load(path: &str) -> Result<Self, RequestError> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| RequestError::Config(format!("can't read {}: {}", path, err)))?;
+        let geonames = Self::parse(&text)?;
+        info!("Loaded {} cities from {}", geonames.cities.len(), path);
+
+        Ok(geonames)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RequestError> {
+        let mut geonames = GeoNames::default();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (city, names) = parse_line(line).ok_or_else(|| {
+                RequestError::Parse(format!("invalid GeoNames line {}", number + 1))
+            })?;
+            geonames.add(city, names);
+        }
+
+        let cities = &geonames.cities;
+        for indexes in geonames.index.values_mut() {
+            indexes.sort_by_key(|index| std::cmp::Reverse(cities[*index].population));
+        }
+        Ok(geonames)
+    }
+
+    fn add(&mut self, city: City, names: Vec<&str>) {
+        let index = self.cities.len();
+        for name in names {
+            let indexes = self.index.entry(normalize_query(name)).or_default();
+            if !indexes.contains(&index) {
+                indexes.push(index);
+            }
+        }
+        self.cities.push(city);
+    }
+
+    /// Cities with the name, the most populated first. The query may end
+    /// with the country code, e.g. `London, CA`
+    pub fn find(&self, query: &str) -> Vec<&City> {
+        let (name, country) = match query.rsplit_once(',') {
+            Some((name, country)) => (name, Some(country.trim().to_uppercase())),
+            None => (query, None),
+        };
+
+        self.index
+            .get(&normalize_query(name))
+            .map(|indexes| {
+                indexes
+                    .iter()
+                    .map(|index| &self.cities[*index])
+                    .filter(|city| {
+                        country
+                            .as_ref()
+                            .is_none_or(|country| city.country_code == *country)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The city nearest to the point
+    pub fn nearest(&self, point: &GeoCoordinate) -> Option<(&City, f64)> {
+        self.cities
+            .iter()
+            .map(|city| (city, city.coordinates.distance_km(point)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+fn parse_line(line: &str) -> Option<(City, Vec<&str>)> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() != COLUMNS {
+        return None;
+    }
+
+    let mut names = vec![columns[1], columns[2]];
+    names.extend(columns[3].split(',').filter(|name| !name.is_empty()));
+    let city = City {
+        name: columns[1].to_string(),
+        country_code: columns[8].to_string(),
+        admin1_code: columns[10].to_string(),
+        coordinates: GeoCoordinate::new(columns[4].parse().ok()?, columns[5].parse().ok()?),
+        population: columns[14].parse().unwrap_or_default(),
+        timezone: columns[17].to_string(),
+    };
+
+    Some((city, names))
+}
+
+#[async_trait]
+impl Geocoder for GeoNames {
+    fn name(&self) -> &'static str {
+        "geonames"
+    }
+
+    /// The cities in the area of the bias go first, the names are not translated
+    async fn search(&self, query: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError> {
+        let mut cities = self.find(query);
+        if let Some(area) = &bias.area {
+            if bias.restrict_to_area {
+                cities.retain(|city| area.contains(&city.coordinates));
+            }
+            cities.sort_by_key(|city| !area.contains(&city.coordinates));
+        }
+        let places: Vec<Place> = cities
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(City::to_place)
+            .collect();
+        if places.is_empty() {
+            return Err(RequestError::NotFound(query.to_string()));
+        }
+        Ok(places)
+    }
+
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError> {
+        match self.nearest(point) {
+            Some((city, distance)) if distance <= MAX_REVERSE_DISTANCE_KM => Ok(city.name.clone()),
+            _ => Err(RequestError::NotFound(format!(
+                "{:.4}, {:.4}",
+                point.latitude, point.longitude
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::SearchArea;
+
+    fn geonames() -> GeoNames {
+        GeoNames::parse(include_str!("../tests/fixtures/geonames/cities.txt")).unwrap()
+    }
+
+    #[test]
+    fn cities_are_found_by_any_name() {
+        let geonames = geonames();
+
+        let moscow = geonames.find("москва");
+        assert_eq!(moscow.len(), 1);
+        assert_eq!(moscow[0].name, "Moscow");
+        assert_eq!(moscow[0].timezone, "Europe/Moscow");
+        assert_eq!(geonames.find("  MOSKVA ")[0].name, "Moscow");
+        assert!(geonames.find("Atlantis").is_empty());
+    }
+
+    #[test]
+    fn most_populated_city_goes_first() {
+        let geonames = geonames();
+
+        let london = geonames.find("London");
+        assert_eq!(london.len(), 2);
+        assert_eq!(london[0].country_code, "GB");
+        assert_eq!(geonames.find("London, ca")[0].country_code, "CA");
+    }
+
+    #[tokio::test]
+    async fn cities_are_returned_as_places() {
+        let places = geonames()
+            .search("Springfield", &SearchBias::default())
+            .await
+            .unwrap();
+        assert_eq!(places.len(), 2);
+        // The labels must differ, or the places can't be chosen
+        assert_eq!(places[0].label(), "Springfield, MO, US");
+        assert_eq!(places[1].label(), "Springfield, IL, US");
+        assert_eq!(places[0].kind.as_deref(), Some("locality"));
+    }
+
+    #[tokio::test]
+    async fn cities_in_the_area_go_first() {
+        let geonames = geonames();
+        let near_toronto = SearchBias {
+            area: Some(SearchArea::around(
+                GeoCoordinate::new(43.65, -79.38),
+                5.0,
+                5.0,
+            )),
+            ..Default::default()
+        };
+        let places = geonames.search("London", &near_toronto).await.unwrap();
+        assert_eq!(places[0].country_code.as_deref(), Some("CA"));
+        assert_eq!(places.len(), 2);
+
+        let only_there = SearchBias {
+            restrict_to_area: true,
+            ..near_toronto
+        };
+        let places = geonames.search("London", &only_there).await.unwrap();
+        assert_eq!(places.len(), 1);
+        assert!(geonames.search("Oslo", &only_there).await.is_err());
+    }
+
+    #[test]
+    fn nearest_city_is_found() {
+        let geonames = geonames();
+
+        let (city, distance) = geonames.nearest(&GeoCoordinate::new(59.95, 10.8)).unwrap();
+        assert_eq!(city.name, "Oslo");
+        assert!(distance < 10.0);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        assert!(GeoNames::parse("3143244\tOslo").is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{IntoUrl, RequestBuilder, Response, StatusCode};
+use tokio::sync::Semaphore;
+
+use crate::error::RequestError;
+
+pub static APP_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " ",
+    env!("CARGO_PKG_REPOSITORY")
+);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// The whole request including the body, so a slow upstream can't stall the handler
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Retries after the first attempt
+const MAX_RETRIES: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+/// A longer `Retry-After` is not waited for, the response is returned as is
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// Concurrent requests to the same host
+const MAX_CONCURRENT_PER_HOST: usize = 4;
+
+/// HTTP client shared by all upstream APIs. Failed requests are retried
+/// with a jittered exponential backoff and the concurrent requests to every
+/// upstream host are limited
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl HttpClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            hosts: Default::default(),
+        }
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    fn host_semaphore(&self, host: &str) -> Arc<Semaphore> {
+        let mut hosts = self.hosts.lock().unwrap_or_else(PoisonError::into_inner);
+        hosts
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(MAX_CONCURRENT_PER_HOST)))
+            .clone()
+    }
+
+    /// Send the request, retrying the network errors, 429 and 5xx responses.
+    /// The last response is returned as is, so the status is still checked
+    /// by the caller
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, RequestError> {
+        let request = request.build()?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let semaphore = self.host_semaphore(&host);
+
+        let mut attempt = 0;
+        loop {
+            let Some(next) = request.try_clone() else {
+                // Streaming bodies can't be sent twice
+                return Ok(self.client.execute(request).await?);
+            };
+
+            let result = {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .map_err(|_| RequestError::Config("HTTP client is closed".to_string()))?;
+                // The URL may have the API key in the query, so it is not logged
+                self.client
+                    .execute(next)
+                    .await
+                    .map_err(reqwest::Error::without_url)
+            };
+
+            let delay = match &result {
+                Ok(res) if is_retryable_status(res.status()) => {
+                    match retry_after(res.headers(), Utc::now()) {
+                        Some(delay) if delay > MAX_RETRY_AFTER => None,
+                        Some(delay) => Some(delay),
+                        None => Some(backoff(attempt)),
+                    }
+                }
+                Ok(_) => None,
+                Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+                    Some(backoff(attempt))
+                }
+                Err(_) => None,
+            };
+
+            match delay {
+                Some(delay) if attempt < MAX_RETRIES => {
+                    let reason = match &result {
+                        Ok(res) => res.status().to_string(),
+                        Err(err) => err.to_string(),
+                    };
+                    warn!(
+                        "Request to {} failed: {}, retry in {:?}",
+                        host, reason, delay
+                    );
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+}
+
+/// Build the HTTP client shared by all upstream APIs
+pub fn build_client() -> reqwest::Result<HttpClient> {
+    info!("Agent: {}", APP_USER_AGENT);
+
+    let client = reqwest::Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    Ok(HttpClient::new(client))
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Exponential backoff with the full jitter: a random delay up to
+/// `BASE_DELAY * 2^attempt`, so the clients don't retry in sync
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+}
+
+/// `Retry-After` in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let time = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (time.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn backoff_grows_up_to_the_limit() {
+        for attempt in 0..10 {
+            let ceiling = (BASE_DELAY * 2u32.pow(attempt)).min(MAX_DELAY);
+            assert!(backoff(attempt) <= ceiling);
+        }
+        assert!(backoff(u32::MAX) <= MAX_DELAY);
+    }
+
+    #[test]
+    fn retry_after_is_parsed() {
+        let now = DateTime::parse_from_rfc3339("2023-10-18T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers, now), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 18 Oct 2023 12:00:05 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::from_secs(5)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 18 Oct 2023 11:00:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers, now), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_not_in_the_errors() {
+        // Nothing listens on the port, so the connection is refused
+        let err = reqwest::Client::new()
+            .get("http://127.0.0.1:1/?apikey=secret")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("secret"));
+        assert!(!RequestError::from(err).to_string().contains("secret"));
+    }
+
+    #[test]
+    fn only_throttling_and_server_errors_are_retried() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::NOT_MODIFIED));
+    }
+}
@@ -0,0 +1,19 @@
+//! Geocoding, weather forecasts and their formatting shared by the Telegram
+//! bot and the command line tools
+
+pub mod display;
+pub mod error;
+pub mod geo;
+pub mod geocache;
+pub mod geonames;
+pub mod http;
+pub mod metno;
+pub mod nominatim;
+pub mod openmeteo;
+pub mod openweathermap;
+pub mod settings;
+pub mod storage;
+pub mod timezone;
+pub mod weather;
+pub mod weather_codes;
+pub mod yandex;
@@ -1,17 +1,95 @@
-use chrono::prelude::{Local, *};
 use dotenv::dotenv;
-use log::info;
+use log::{error, info};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashSet;
 use std::env;
+use std::path::Path;
+use std::sync::Arc;
 use teloxide::{
+    dispatching::{dialogue, dialogue::InMemStorage, UpdateHandler},
     prelude::*,
-    types::{ButtonRequest, KeyboardButton, KeyboardMarkup},
+    types::{
+        ButtonRequest, InlineKeyboardButton, InlineKeyboardMarkup, KeyboardButton, KeyboardMarkup,
+        KeyboardRemove, Location, MessageId,
+    },
     utils::command::BotCommands,
 };
 
-mod geo;
-mod weather;
-pub mod weather_codes;
-use weather::{Forecast, Weather};
+mod alerts;
+mod rules;
+mod subscriptions;
+
+use alerts::{MetAlerts, SentAlertStorage};
+use rules::{Rule, RuleKey, RuleState, RuleStorage};
+use subscriptions::{Subscription, SubscriptionKey, SubscriptionStorage};
+use weather_bot::display::{
+    display_daily_forecast_telegram, display_error_telegram, display_forecast_telegram,
+    display_place_header_telegram,
+};
+use weather_bot::error::RequestError;
+use weather_bot::geo::{place_at, GeoCoordinate, Geocoder, Place, SearchBias, MAX_CANDIDATES};
+use weather_bot::geocache::{CachedGeocoder, GeoCacheStorage};
+use weather_bot::geonames::GeoNames;
+use weather_bot::settings::{HomeLocation, SettingsKey, UserSettings};
+use weather_bot::storage::{AsyncStorage, JsonFileStorage, MemoryStorage, Storage};
+use weather_bot::timezone::TimezoneFinder;
+use weather_bot::weather::{FailoverProvider, Weather};
+use weather_bot::{error, geo, geocache, http, settings, timezone, weather};
+
+const DEFAULT_FORECAST_DAYS: usize = 3;
+const MAX_FORECAST_DAYS: usize = 9;
+/// Kind of the cities found by Yandex and GeoNames
+const LOCALITY_KIND: &str = "locality";
+
+type BotDialogue = Dialogue<State, InMemStorage<State>>;
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+type SettingsStorage = Arc<dyn Storage<SettingsKey, UserSettings>>;
+
+/// Forecast sources shared by the handlers
+#[derive(Clone)]
+struct Forecaster {
+    provider: Arc<FailoverProvider>,
+    timezones: Arc<TimezoneFinder>,
+}
+
+impl Forecaster {
+    /// Providers in the order preferred by the user
+    fn provider(&self, settings: &UserSettings) -> FailoverProvider {
+        self.provider.preferring(settings.provider.as_deref())
+    }
+}
+
+/// Telegram ids of the users allowed to run the admin commands
+#[derive(Clone, Default)]
+struct AdminIds(Arc<Vec<u64>>);
+
+/// Conversation state of the chat
+#[derive(Clone, Default)]
+pub enum State {
+    #[default]
+    Start,
+    ReceiveCity,
+    /// The city name is ambiguous, the places are offered with the inline keyboard
+    /// of the message
+    ChooseCity {
+        places: Vec<Place>,
+        action: ChoiceAction,
+        message_id: MessageId,
+    },
+}
+
+/// What to send once the place is known
+#[derive(Clone)]
+pub enum ChoiceAction {
+    Forecast,
+    DailyForecast(usize),
+}
+
+/// Result of looking up the city the user typed
+enum CityLookup {
+    Found(Place),
+    Ambiguous(Vec<Place>),
+}
 
 /// These commands are supported:
 #[derive(BotCommands, Clone)]
@@ -23,52 +101,417 @@ enum Command {
     City(String),
     #[command(description = "Get the weather in the send location")]
     Location,
+    #[command(description = "Get the forecast by day: /forecast [days] <city>")]
+    Forecast(String),
+    #[command(description = "Show or change the settings: /settings [name value]")]
+    Settings(String),
 }
 
-fn round_to_near_hour(time: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
-    let hour = time.hour() + 1;
+/// Daily forecast commands
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+enum SubscriptionCommand {
+    #[command(description = "Get the forecast every day: /subscribe <city> <HH:MM>")]
+    Subscribe(String),
+    #[command(description = "Stop the daily forecast: /unsubscribe [city]")]
+    Unsubscribe(String),
+    #[command(description = "Show the daily forecasts of this chat")]
+    Subscriptions,
+}
 
-    FixedOffset::fix(time.offset())
-        .with_ymd_and_hms(time.year(), time.month(), time.day(), hour, 0, 0)
-        .unwrap()
+/// Forecast alert rule commands
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+enum AlertCommand {
+    #[command(description = "Notify on the forecast at home: /alert temp < -10 [today|tomorrow]")]
+    Alert(String),
+    #[command(description = "Remove the alert rules: /unalert [rule]")]
+    Unalert(String),
+    #[command(description = "Show your alert rules in this chat")]
+    Alerts,
+}
+
+/// Commands of the bot admins, hidden from the help
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+enum AdminCommand {
+    #[command(description = "off")]
+    Stats,
 }
 
-fn display_forecast_telegram(forecast: &Forecast) -> String {
+fn help_text() -> String {
     format!(
-        "Air Pressure: {} mmHg\nTemperature: {}℃\nHumidity: {}% \nCloud: {}%\nWind Direction: {}°\nWind Speed: {} m/s",
-        (forecast.air_pressure_at_sea_level * 0.13332239).round(),
-        forecast.air_temperature,
-        forecast.relative_humidity,
-        forecast.cloud_area_fraction,
-        forecast.wind_from_direction,
-        forecast.wind_speed,
+        "{}\n{}\n{}",
+        Command::descriptions(),
+        SubscriptionCommand::descriptions(),
+        AlertCommand::descriptions()
     )
 }
 
-async fn answer(bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
-    match cmd {
-        Command::Help => {
-            bot.send_message(msg.chat.id, Command::descriptions().to_string())
-                .await?
+const SETTINGS_HELP: &str = "Change a setting with /settings <name> <value>:
+temperature c|f
+pressure mmhg|hpa
+wind ms|kmh|mph
+language en|ru
+home <city>
+timezone <IANA name>, e.g. Europe/Moscow
+provider <name>
+search anywhere|home
+reset";
+
+/// Split `/forecast` arguments into the number of days and the city
+fn parse_forecast_args(args: &str) -> (usize, &str) {
+    let args = args.trim();
+    match args.split_once(' ') {
+        Some((days, city)) => match days.parse::<usize>() {
+            Ok(days) => (days.clamp(1, MAX_FORECAST_DAYS), city.trim()),
+            Err(_) => (DEFAULT_FORECAST_DAYS, args),
+        },
+        None => match args.parse::<usize>() {
+            Ok(days) => (days.clamp(1, MAX_FORECAST_DAYS), ""),
+            Err(_) => (DEFAULT_FORECAST_DAYS, args),
+        },
+    }
+}
+
+fn settings_key(msg: &Message) -> SettingsKey {
+    SettingsKey {
+        chat_id: msg.chat.id.0,
+        user_id: msg.from().map(|user| user.id.0).unwrap_or_default(),
+    }
+}
+
+fn load_settings(settings_storage: &SettingsStorage, key: &SettingsKey) -> UserSettings {
+    match settings_storage.get(key) {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(err) => {
+            error!("Can't load the settings: {}", err);
+            UserSettings::default()
+        }
+    }
+}
+
+/// The forecast for the current hour, `title` names the place in the header
+async fn forecast_message(
+    forecaster: &Forecaster,
+    title: Option<&str>,
+    geo_point: GeoCoordinate,
+    settings: &UserSettings,
+) -> Result<String, RequestError> {
+    let weather = Weather::new(&forecaster.provider(settings), geo_point).await?;
+
+    info!("Request the forecast for: {}", weather);
+
+    // The times are shown in the timezone of the place
+    let local_time = forecaster.timezones.now_at(&geo_point);
+    let forecast = weather.get_forecast_at(&local_time)?;
+
+    Ok(format!(
+        "{}\n\n{}",
+        display_place_header_telegram(title, &geo_point, &local_time),
+        display_forecast_telegram(&forecast, &settings.units)
+    ))
+}
+
+/// Find the places with the name. Only the places of the same kind as the
+/// best match are offered, so a street doesn't compete with the city.
+/// Coordinates are named by reverse geocoding
+async fn lookup_city(
+    geocoder: &CachedGeocoder,
+    city: &str,
+    bias: &SearchBias,
+) -> Result<CityLookup, RequestError> {
+    if let Some(point) = GeoCoordinate::parse(city) {
+        return Ok(CityLookup::Found(place_at(geocoder, point).await));
+    }
+
+    let mut places = geocoder.search(city, bias).await?;
+    // The streets named after the found city are not offered. The kinds of
+    // Nominatim (city, town, village) are not compared, they are all cities
+    let localities_only = places
+        .first()
+        .is_some_and(|place| place.kind.as_deref() == Some(LOCALITY_KIND));
+    let mut labels = HashSet::new();
+    places.retain(|place| {
+        (!localities_only || place.kind.as_deref() == Some(LOCALITY_KIND))
+            && labels.insert(place.label())
+    });
+    places.truncate(MAX_CANDIDATES);
+
+    match places.len() {
+        0 => Err(RequestError::NotFound(city.to_string())),
+        1 => Ok(CityLookup::Found(places.remove(0))),
+        _ => Ok(CityLookup::Ambiguous(places)),
+    }
+}
+
+/// Name and coordinates of the city for the stored settings
+async fn resolve_city(
+    geocoder: &CachedGeocoder,
+    city: &str,
+    bias: &SearchBias,
+) -> Result<(String, GeoCoordinate), RequestError> {
+    match GeoCoordinate::parse(city) {
+        Some(point) => Ok((place_at(geocoder, point).await.name, point)),
+        None => Ok((city.to_string(), geocoder.geocode(city, bias).await?)),
+    }
+}
+
+async fn place_message(
+    forecaster: &Forecaster,
+    place: &Place,
+    action: &ChoiceAction,
+    settings: &UserSettings,
+) -> Result<String, RequestError> {
+    info!("Request the forecast at: {}", place.label());
+    let title = place.title();
+    match action {
+        ChoiceAction::Forecast => {
+            forecast_message(forecaster, Some(&title), place.coordinates, settings).await
         }
+        ChoiceAction::DailyForecast(days) => {
+            daily_forecast_message(forecaster, Some(&title), place.coordinates, *days, settings)
+                .await
+        }
+    }
+}
+
+async fn daily_forecast_message(
+    forecaster: &Forecaster,
+    title: Option<&str>,
+    geo_point: GeoCoordinate,
+    days: usize,
+    settings: &UserSettings,
+) -> Result<String, RequestError> {
+    let weather = Weather::new(&forecaster.provider(settings), geo_point).await?;
+
+    let local_time = forecaster.timezones.now_at(&geo_point);
+    let timezone = forecaster.timezones.find(&geo_point);
+    let mut daily_forecasts = weather.get_daily_forecasts(&timezone);
+    daily_forecasts.truncate(days);
+
+    Ok(format!(
+        "{}\n\n{}",
+        display_place_header_telegram(title, &geo_point, &local_time),
+        display_daily_forecast_telegram(&daily_forecasts, &settings.units)
+    ))
+}
+
+/// Ask the user to choose one of the places
+async fn offer_choice(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    places: Vec<Place>,
+    action: ChoiceAction,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>> {
+    let buttons = places.iter().enumerate().map(|(index, place)| {
+        vec![InlineKeyboardButton::callback(
+            place.label(),
+            format!("place:{}", index),
+        )]
+    });
+    let keyboard = InlineKeyboardMarkup::new(buttons);
+
+    let msg = bot
+        .send_message(dialogue.chat_id(), "Which one do you mean?")
+        .reply_markup(keyboard)
+        .await?;
+    dialogue
+        .update(State::ChooseCity {
+            places,
+            action,
+            message_id: msg.id,
+        })
+        .await?;
+
+    Ok(msg)
+}
+
+/// Send the forecast in the city or let the user choose the place first
+async fn reply_city(
+    bot: &Bot,
+    dialogue: &BotDialogue,
+    geocoder: &CachedGeocoder,
+    forecaster: &Forecaster,
+    city: &str,
+    action: ChoiceAction,
+    settings: &UserSettings,
+) -> Result<Message, Box<dyn std::error::Error + Send + Sync>> {
+    info!("Request the forecast in the city: {}", city);
+    let text = match lookup_city(geocoder, city, &settings.search_bias()).await {
+        Ok(CityLookup::Found(place)) => {
+            reply_text(place_message(forecaster, &place, &action, settings).await)
+        }
+        Ok(CityLookup::Ambiguous(places)) => {
+            return offer_choice(bot, dialogue, places, action).await
+        }
+        Err(err) => reply_text(Err(err)),
+    };
+
+    Ok(bot
+        .send_message(dialogue.chat_id(), text)
+        .disable_web_page_preview(true)
+        .await?)
+}
+
+/// Turn the result of a request into the reply text
+fn reply_text(result: Result<String, RequestError>) -> String {
+    result.unwrap_or_else(|err| {
+        error!("Request failed: {}", err);
+        display_error_telegram(&err)
+    })
+}
+
+fn chat_subscriptions(
+    subscription_storage: &SubscriptionStorage,
+    chat_id: ChatId,
+) -> Result<Vec<(SubscriptionKey, Subscription)>, error::StorageError> {
+    let mut subscriptions: Vec<(SubscriptionKey, Subscription)> = subscription_storage
+        .all()?
+        .into_iter()
+        .filter(|(key, _)| key.chat_id == chat_id.0)
+        .collect();
+    subscriptions.sort_by_key(|(_, subscription)| subscription.time);
+
+    Ok(subscriptions)
+}
+
+fn user_rules(
+    rule_storage: &RuleStorage,
+    msg: &Message,
+) -> Result<Vec<(RuleKey, RuleState)>, error::StorageError> {
+    let settings_key = settings_key(msg);
+    let mut rules: Vec<(RuleKey, RuleState)> = rule_storage
+        .all()?
+        .into_iter()
+        .filter(|(key, _)| {
+            key.chat_id == settings_key.chat_id && key.user_id == settings_key.user_id
+        })
+        .collect();
+    rules.sort_by(|(a, _), (b, _)| a.rule.cmp(&b.rule));
+
+    Ok(rules)
+}
+
+/// Apply `/settings <name> <value>` and return the reply
+async fn change_settings(
+    geocoder: &CachedGeocoder,
+    provider: &FailoverProvider,
+    settings: &mut UserSettings,
+    args: &str,
+) -> Result<String, String> {
+    let (name, value) = args.split_once(' ').unwrap_or((args, ""));
+    let value = value.trim();
+
+    match name.to_lowercase().as_str() {
+        "temperature" => settings.units.temperature = value.parse()?,
+        "pressure" => settings.units.pressure = value.parse()?,
+        "wind" => settings.units.wind_speed = value.parse()?,
+        "language" => settings.language = value.parse()?,
+        "search" => settings.search_scope = value.parse()?,
+        "timezone" => settings.timezone = Some(settings::parse_timezone(value)?),
+        "provider" => {
+            let names = provider.names();
+            match names.iter().find(|name| name.eq_ignore_ascii_case(value)) {
+                Some(name) => settings.provider = Some(name.to_string()),
+                None => {
+                    return Err(format!(
+                        "Unknown provider \"{}\". Available: {}",
+                        value,
+                        names.join(", ")
+                    ))
+                }
+            }
+        }
+        "home" if value.is_empty() => return Err("Please enter the home city".to_string()),
+        "home" => {
+            let (name, coordinates) = resolve_city(geocoder, value, &settings.search_bias())
+                .await
+                .map_err(|err| display_error_telegram(&err))?;
+            settings.home = Some(HomeLocation { name, coordinates });
+        }
+        "reset" => *settings = UserSettings::default(),
+        _ => return Err(SETTINGS_HELP.to_string()),
+    }
+
+    Ok(format!("Settings are saved\n\n{}", settings))
+}
+
+fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    use dptree::case;
+
+    let command_handler = teloxide::filter_command::<Command, _>().endpoint(answer);
+    let subscription_command_handler =
+        teloxide::filter_command::<SubscriptionCommand, _>().endpoint(answer_subscriptions);
+    let alert_command_handler =
+        teloxide::filter_command::<AlertCommand, _>().endpoint(answer_alerts);
+    let admin_command_handler =
+        teloxide::filter_command::<AdminCommand, _>().endpoint(answer_admin);
+
+    let message_handler = Update::filter_message()
+        .branch(command_handler)
+        .branch(subscription_command_handler)
+        .branch(alert_command_handler)
+        .branch(admin_command_handler)
+        .branch(
+            dptree::filter_map(|msg: Message| msg.location().copied()).endpoint(answer_location),
+        )
+        .branch(case![State::ReceiveCity].endpoint(receive_city));
+
+    let callback_query_handler = Update::filter_callback_query()
+        .branch(
+            case![State::ChooseCity {
+                places,
+                action,
+                message_id
+            }]
+            .endpoint(receive_choice),
+        )
+        .endpoint(outdated_choice);
+
+    dialogue::enter::<Update, InMemStorage<State>, State, _>()
+        .branch(message_handler)
+        .branch(callback_query_handler)
+}
+
+async fn answer(
+    bot: Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+    cmd: Command,
+    geocoder: Arc<CachedGeocoder>,
+    forecaster: Forecaster,
+    settings_storage: SettingsStorage,
+) -> HandlerResult {
+    let settings = load_settings(&settings_storage, &settings_key(&msg));
+
+    match cmd {
+        Command::Help => bot.send_message(msg.chat.id, help_text()).await?,
         Command::City(city) => {
-            if city.is_empty() {
-                bot.send_message(
-                    msg.chat.id,
-                    "Please enter any city name. Example: \n/city Moscow",
+            if !city.is_empty() {
+                dialogue.reset().await?;
+                reply_city(
+                    &bot,
+                    &dialogue,
+                    &geocoder,
+                    &forecaster,
+                    &city,
+                    ChoiceAction::Forecast,
+                    &settings,
                 )
                 .await?
+            } else if let Some(home) = &settings.home {
+                info!("Request the forecast at home: {}", home.name);
+                let text = reply_text(
+                    forecast_message(&forecaster, Some(&home.name), home.coordinates, &settings)
+                        .await,
+                );
+                bot.send_message(msg.chat.id, text)
+                    .disable_web_page_preview(true)
+                    .await?
             } else {
-                let geo_point = geo::request_geo_api(city.clone()).await.unwrap();
-                let city_weather = Weather::new(geo_point).await.unwrap();
-
-                info!("Request the forecast in the city: {}", city_weather);
-
-                let local_time = Local::now().fixed_offset();
-                let rounded_time = round_to_near_hour(&local_time);
-                let forecast = city_weather.get_forecast_for_hour(&rounded_time).unwrap();
-
-                bot.send_message(msg.chat.id, display_forecast_telegram(forecast))
+                dialogue.update(State::ReceiveCity).await?;
+                bot.send_message(msg.chat.id, "Please enter any city name. Example: \nMoscow")
                     .await?
             }
         }
@@ -94,11 +537,374 @@ async fn answer(bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
             .reply_markup(keyboard)
             .await?
         }
+
+        Command::Forecast(args) => {
+            let (days, city) = parse_forecast_args(&args);
+            if !city.is_empty() {
+                reply_city(
+                    &bot,
+                    &dialogue,
+                    &geocoder,
+                    &forecaster,
+                    city,
+                    ChoiceAction::DailyForecast(days),
+                    &settings,
+                )
+                .await?
+            } else {
+                let text = match &settings.home {
+                    Some(home) => reply_text(
+                        daily_forecast_message(
+                            &forecaster,
+                            Some(&home.name),
+                            home.coordinates,
+                            days,
+                            &settings,
+                        )
+                        .await,
+                    ),
+                    None => "Please enter any city name. Example: \n/forecast 5 Moscow".to_string(),
+                };
+                bot.send_message(msg.chat.id, text)
+                    .disable_web_page_preview(true)
+                    .await?
+            }
+        }
+
+        Command::Settings(args) => {
+            let args = args.trim();
+            let text = if args.is_empty() {
+                format!("{}\n\n{}", settings, SETTINGS_HELP)
+            } else {
+                let mut settings = settings;
+                match change_settings(&geocoder, &forecaster.provider, &mut settings, args).await {
+                    Ok(text) => match settings_storage
+                        .set_async(settings_key(&msg), settings)
+                        .await
+                    {
+                        Ok(()) => text,
+                        Err(err) => {
+                            error!("Can't save the settings: {}", err);
+                            "Can't save the settings. Please try again later".to_string()
+                        }
+                    },
+                    Err(text) => text,
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+    };
+
+    Ok(())
+}
+
+async fn answer_subscriptions(
+    bot: Bot,
+    msg: Message,
+    cmd: SubscriptionCommand,
+    geocoder: Arc<CachedGeocoder>,
+    settings_storage: SettingsStorage,
+    subscription_storage: SubscriptionStorage,
+) -> HandlerResult {
+    match cmd {
+        SubscriptionCommand::Subscribe(args) => {
+            let settings = load_settings(&settings_storage, &settings_key(&msg));
+            let text = match subscriptions::parse_subscribe_args(&args) {
+                Ok((city, time)) => match resolve_city(&geocoder, city, &settings.search_bias())
+                    .await
+                {
+                    Ok((city, coordinates)) => {
+                        let subscription = Subscription {
+                            coordinates,
+                            time,
+                            timezone: settings.timezone.clone(),
+                            units: settings.units,
+                            last_sent: None,
+                        };
+                        let key = SubscriptionKey::new(msg.chat.id.0, &city);
+                        match subscription_storage.set_async(key, subscription).await {
+                            Ok(()) => format!(
+                                "The forecast for {} will be sent every day at {}",
+                                city,
+                                time.format("%H:%M")
+                            ),
+                            Err(err) => {
+                                error!("Can't save the subscription: {}", err);
+                                "Can't save the subscription. Please try again later".to_string()
+                            }
+                        }
+                    }
+                    Err(err) => reply_text(Err(err)),
+                },
+                Err(usage) => usage,
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+
+        SubscriptionCommand::Unsubscribe(city) => {
+            let city = city.trim().to_string();
+            let chat_id = msg.chat.id.0;
+            let removed = subscription_storage
+                .retain_async(move |key, _| {
+                    key.chat_id != chat_id
+                        || !(city.is_empty() || key.city.eq_ignore_ascii_case(&city))
+                })
+                .await;
+
+            let text = match removed {
+                Ok(0) => "There is no such subscription".to_string(),
+                Ok(removed) => format!("Removed subscriptions: {}", removed),
+                Err(err) => {
+                    error!("Can't remove the subscription: {}", err);
+                    "Can't remove the subscription. Please try again later".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+
+        SubscriptionCommand::Subscriptions => {
+            let text = match chat_subscriptions(&subscription_storage, msg.chat.id) {
+                Ok(subscriptions) => subscriptions::display_subscriptions_telegram(&subscriptions),
+                Err(err) => {
+                    error!("Can't load the subscriptions: {}", err);
+                    "Can't load the subscriptions. Please try again later".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+    };
+
+    Ok(())
+}
+
+async fn answer_alerts(
+    bot: Bot,
+    msg: Message,
+    cmd: AlertCommand,
+    settings_storage: SettingsStorage,
+    rule_storage: RuleStorage,
+) -> HandlerResult {
+    match cmd {
+        AlertCommand::Alert(args) => {
+            let settings = load_settings(&settings_storage, &settings_key(&msg));
+            let text = match args.parse::<Rule>() {
+                Ok(_) if settings.home.is_none() => {
+                    "Please set the home city first: /settings home <city>".to_string()
+                }
+                Ok(rule) => {
+                    let key = RuleKey {
+                        chat_id: msg.chat.id.0,
+                        user_id: settings_key(&msg).user_id,
+                        rule: rule.to_string(),
+                    };
+                    let text = format!("You will be notified on {}", key.rule);
+                    match rule_storage
+                        .set_async(key, RuleState::new(rule, settings.units))
+                        .await
+                    {
+                        Ok(()) => text,
+                        Err(err) => {
+                            error!("Can't save the alert rule: {}", err);
+                            "Can't save the alert rule. Please try again later".to_string()
+                        }
+                    }
+                }
+                Err(usage) => usage,
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+
+        AlertCommand::Unalert(args) => {
+            let remove_all = args.trim().is_empty();
+            let rule = args.parse::<Rule>().map(|rule| rule.to_string()).ok();
+            let user = settings_key(&msg);
+            let removed = rule_storage
+                .retain_async(move |key, _| {
+                    key.chat_id != user.chat_id
+                        || key.user_id != user.user_id
+                        || !(remove_all || rule.as_ref() == Some(&key.rule))
+                })
+                .await;
+
+            let text = match removed {
+                Ok(0) => "There is no such alert rule".to_string(),
+                Ok(removed) => format!("Removed alert rules: {}", removed),
+                Err(err) => {
+                    error!("Can't remove the alert rule: {}", err);
+                    "Can't remove the alert rule. Please try again later".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+
+        AlertCommand::Alerts => {
+            let text = match user_rules(&rule_storage, &msg) {
+                Ok(rules) => rules::display_rules_telegram(&rules),
+                Err(err) => {
+                    error!("Can't load the alert rules: {}", err);
+                    "Can't load the alert rules. Please try again later".to_string()
+                }
+            };
+            bot.send_message(msg.chat.id, text).await?
+        }
+    };
+
+    Ok(())
+}
+
+async fn answer_admin(
+    bot: Bot,
+    msg: Message,
+    cmd: AdminCommand,
+    geocoder: Arc<CachedGeocoder>,
+    admin_ids: AdminIds,
+) -> HandlerResult {
+    let user_id = msg.from().map(|user| user.id.0);
+    if !user_id.is_some_and(|user_id| admin_ids.0.contains(&user_id)) {
+        info!("Admin command from unknown user {:?}", user_id);
+        return Ok(());
+    }
+
+    match cmd {
+        AdminCommand::Stats => {
+            bot.send_message(msg.chat.id, geocoder.stats().to_string())
+                .await?
+        }
+    };
+
+    Ok(())
+}
+
+async fn receive_city(
+    bot: Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+    geocoder: Arc<CachedGeocoder>,
+    forecaster: Forecaster,
+    settings_storage: SettingsStorage,
+) -> HandlerResult {
+    match msg.text() {
+        Some(city) => {
+            dialogue.reset().await?;
+            let settings = load_settings(&settings_storage, &settings_key(&msg));
+            reply_city(
+                &bot,
+                &dialogue,
+                &geocoder,
+                &forecaster,
+                city,
+                ChoiceAction::Forecast,
+                &settings,
+            )
+            .await?;
+        }
+        None => {
+            bot.send_message(msg.chat.id, "Please send the city name as text")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_choice(
+    bot: Bot,
+    dialogue: BotDialogue,
+    q: CallbackQuery,
+    (places, action, message_id): (Vec<Place>, ChoiceAction, MessageId),
+    forecaster: Forecaster,
+    settings_storage: SettingsStorage,
+) -> HandlerResult {
+    // The keyboards of the previous lookups point to other places
+    if q.message.as_ref().map(|msg| msg.id) != Some(message_id) {
+        return outdated_choice(bot, q).await;
+    }
+    bot.answer_callback_query(q.id).await?;
+
+    let place = q
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix("place:"))
+        .and_then(|index| index.parse::<usize>().ok())
+        .and_then(|index| places.get(index));
+    let (Some(place), Some(msg)) = (place, q.message) else {
+        return Ok(());
+    };
+    dialogue.reset().await?;
+
+    // Replace the keyboard with the chosen place
+    bot.edit_message_text(msg.chat.id, msg.id, place.label())
+        .await?;
+
+    let settings = load_settings(
+        &settings_storage,
+        &SettingsKey {
+            chat_id: msg.chat.id.0,
+            user_id: q.from.id.0,
+        },
+    );
+    let text = reply_text(place_message(&forecaster, place, &action, &settings).await);
+    bot.send_message(msg.chat.id, text)
+        .disable_web_page_preview(true)
+        .await?;
+
+    Ok(())
+}
+
+/// Answer the buttons of the keyboards which are not waited for, or the
+/// client keeps showing the progress
+async fn outdated_choice(bot: Bot, q: CallbackQuery) -> HandlerResult {
+    bot.answer_callback_query(q.id)
+        .text("This choice is outdated, please search the city again")
+        .await?;
+
+    Ok(())
+}
+
+async fn answer_location(
+    bot: Bot,
+    msg: Message,
+    location: Location,
+    geocoder: Arc<CachedGeocoder>,
+    forecaster: Forecaster,
+    settings_storage: SettingsStorage,
+) -> HandlerResult {
+    let geo_point = GeoCoordinate::new(location.latitude, location.longitude);
+
+    info!("Request the forecast in the location: {:?}", geo_point);
+
+    let settings = load_settings(&settings_storage, &settings_key(&msg));
+    let name = match geocoder.reverse(&geo_point).await {
+        Ok(name) => Some(name),
+        Err(err) => {
+            error!("Can't find the name of the location: {}", err);
+            None
+        }
     };
+    let text =
+        reply_text(forecast_message(&forecaster, name.as_deref(), geo_point, &settings).await);
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(KeyboardRemove::new())
+        .disable_web_page_preview(true)
+        .await?;
 
     Ok(())
 }
 
+fn open_storage<K, V>(
+    data_dir: &Option<String>,
+    file_name: &str,
+) -> Result<Arc<dyn Storage<K, V>>, error::StorageError>
+where
+    K: Eq + std::hash::Hash + Clone + Send + Serialize + DeserializeOwned + 'static,
+    V: Clone + Send + Serialize + DeserializeOwned + 'static,
+{
+    Ok(match data_dir {
+        Some(data_dir) => Arc::new(JsonFileStorage::open(Path::new(data_dir).join(file_name))?),
+        None => Arc::new(MemoryStorage::new()),
+    })
+}
+
 #[tokio::main]
 async fn main() {
     let _ = dotenv().ok();
@@ -120,11 +926,155 @@ async fn main() {
     let about_me = bot.get_me().await.unwrap();
     println!("INFO: This chat bot info: \n{:?}", &about_me);
 
-    let commands = Command::bot_commands();
+    let mut commands = Command::bot_commands();
+    commands.extend(SubscriptionCommand::bot_commands());
+    commands.extend(AlertCommand::bot_commands());
     match bot.set_my_commands(commands).await {
         Ok(_) => println!("INFO: set my commands"),
         Err(err) => eprintln!("ERROR: error set my commands: {}", err),
     }
 
-    Command::repl(bot, answer).await;
+    // One client for all upstream APIs, so the connections and the limits are shared
+    let client = match http::build_client() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Can't build the HTTP client: {}", err);
+            return;
+        }
+    };
+    // The dump is shared by the offline geocoder and the timezone lookup
+    let geonames = match env::var("GEONAMES_FILE") {
+        Ok(path) => match GeoNames::load(&path) {
+            Ok(geonames) => Some(Arc::new(geonames)),
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        },
+        Err(_) => None,
+    };
+    let timezones = match timezone::finder_from_env(geonames.clone()) {
+        Ok(timezones) => Arc::new(timezones),
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+    let geocoder_chain = match geo::geocoder_from_env(client.clone(), geonames.clone()) {
+        Ok(geocoder) => geocoder,
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+    let provider = match weather::provider_from_env(client.clone()) {
+        Ok(provider) => Arc::new(provider),
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+
+    // Without DATA_DIR the state lives in memory until the bot restarts
+    let data_dir = env::var("DATA_DIR").ok();
+    let settings_storage: SettingsStorage = match open_storage(&data_dir, "settings.json") {
+        Ok(storage) => storage,
+        Err(err) => {
+            error!("Can't open the settings storage: {}", err);
+            return;
+        }
+    };
+    let subscription_storage: SubscriptionStorage =
+        match open_storage(&data_dir, "subscriptions.json") {
+            Ok(storage) => storage,
+            Err(err) => {
+                error!("Can't open the subscriptions storage: {}", err);
+                return;
+            }
+        };
+
+    let sent_alert_storage: SentAlertStorage = match open_storage(&data_dir, "alerts.json") {
+        Ok(storage) => storage,
+        Err(err) => {
+            error!("Can't open the sent alerts storage: {}", err);
+            return;
+        }
+    };
+
+    let geocache_storage: GeoCacheStorage = match open_storage(&data_dir, "geocache.json") {
+        Ok(storage) => storage,
+        Err(err) => {
+            error!("Can't open the geocoding cache: {}", err);
+            return;
+        }
+    };
+    let geocache_ttl_days = match env::var("GEOCACHE_TTL_DAYS") {
+        Ok(value) => match value.parse::<i64>() {
+            Ok(days) => days,
+            Err(_) => {
+                error!("GEOCACHE_TTL_DAYS must be a number of days");
+                return;
+            }
+        },
+        Err(_) => geocache::DEFAULT_TTL_DAYS,
+    };
+    let geocoder = Arc::new(CachedGeocoder::new(
+        geocoder_chain,
+        geocache_storage,
+        chrono::Duration::days(geocache_ttl_days),
+    ));
+    let admin_ids = AdminIds(Arc::new(
+        env::var("ADMIN_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect(),
+    ));
+    let rule_storage: RuleStorage = match open_storage(&data_dir, "rules.json") {
+        Ok(storage) => storage,
+        Err(err) => {
+            error!("Can't open the alert rules storage: {}", err);
+            return;
+        }
+    };
+
+    tokio::spawn(alerts::run(
+        bot.clone(),
+        Arc::new(MetAlerts::new(client)),
+        subscription_storage.clone(),
+        sent_alert_storage,
+    ));
+
+    tokio::spawn(subscriptions::run(
+        bot.clone(),
+        provider.clone(),
+        timezones.clone(),
+        subscription_storage.clone(),
+    ));
+    tokio::spawn(rules::run(
+        bot.clone(),
+        provider.clone(),
+        timezones.clone(),
+        settings_storage.clone(),
+        rule_storage.clone(),
+    ));
+    let forecaster = Forecaster {
+        provider,
+        timezones,
+    };
+
+    Dispatcher::builder(bot, schema())
+        .dependencies(dptree::deps![
+            geocoder,
+            forecaster,
+            settings_storage,
+            subscription_storage,
+            rule_storage,
+            admin_ids,
+            InMemStorage::<State>::new()
+        ])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
 }
@@ -0,0 +1,138 @@
+//! Models of the met.no Locationforecast 2.0 GeoJSON response.
+//! See <https://api.met.no/weatherapi/locationforecast/2.0/documentation>
+
+use log::debug;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+
+use crate::error::RequestError;
+use crate::weather::Forecasts;
+
+/// Forecasts of the response, the units must be the documented ones
+pub fn parse_response(text_response: &str) -> Result<Forecasts, RequestError> {
+    let response: LocationForecast = serde_json::from_str(text_response)?;
+    let properties = response.properties;
+    debug!("Forecast updated at {}", properties.meta.updated_at);
+    if let Some((name, unit)) = properties.meta.units.unexpected() {
+        return Err(RequestError::Parse(format!(
+            "unexpected unit of {}: {}",
+            name, unit
+        )));
+    }
+
+    Ok(properties
+        .timeseries
+        .into_iter()
+        .map(|step| (step.time, step.data.into()))
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LocationForecast {
+    pub properties: ForecastProperties,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForecastProperties {
+    pub meta: ForecastMeta,
+    pub timeseries: Vec<ForecastTimeStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForecastMeta {
+    pub updated_at: DateTime<Utc>,
+    pub units: ForecastUnits,
+}
+
+/// Units of the values, only the ones the forecast is converted from
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct ForecastUnits {
+    pub air_pressure_at_sea_level: Option<String>,
+    pub air_temperature: Option<String>,
+    pub precipitation_amount: Option<String>,
+    pub wind_speed: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForecastTimeStep {
+    pub time: DateTime<FixedOffset>,
+    pub data: ForecastData,
+}
+
+/// The periods end together with the horizon, so the last steps have none
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForecastData {
+    pub instant: InstantData,
+    pub next_1_hours: Option<ForecastPeriod>,
+    pub next_6_hours: Option<ForecastPeriod>,
+    pub next_12_hours: Option<ForecastPeriod>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct InstantData {
+    pub details: InstantDetails,
+}
+
+/// The optional values are only in the `complete` product,
+/// and not for the whole horizon
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct InstantDetails {
+    pub air_pressure_at_sea_level: f64,
+    pub air_temperature: f64,
+    pub cloud_area_fraction: f64,
+    pub relative_humidity: f64,
+    pub wind_from_direction: f64,
+    pub wind_speed: f64,
+    pub wind_speed_of_gust: Option<f64>,
+    pub dew_point_temperature: Option<f64>,
+    pub fog_area_fraction: Option<f64>,
+    pub ultraviolet_index_clear_sky: Option<f64>,
+    pub cloud_area_fraction_high: Option<f64>,
+    pub cloud_area_fraction_medium: Option<f64>,
+    pub cloud_area_fraction_low: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ForecastPeriod {
+    pub summary: PeriodSummary,
+    #[serde(default)]
+    pub details: PeriodDetails,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PeriodSummary {
+    pub symbol_code: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct PeriodDetails {
+    pub precipitation_amount: Option<f64>,
+    pub precipitation_amount_min: Option<f64>,
+    pub precipitation_amount_max: Option<f64>,
+    pub probability_of_precipitation: Option<f64>,
+}
+
+impl ForecastUnits {
+    /// The forecast is shown in the units converted from these,
+    /// so another unit must not be taken for them
+    pub fn unexpected(&self) -> Option<(&'static str, &str)> {
+        [
+            (
+                "air_pressure_at_sea_level",
+                &self.air_pressure_at_sea_level,
+                "hPa",
+            ),
+            ("air_temperature", &self.air_temperature, "celsius"),
+            ("precipitation_amount", &self.precipitation_amount, "mm"),
+            ("wind_speed", &self.wind_speed, "m/s"),
+        ]
+        .into_iter()
+        .find_map(|(name, unit, expected)| match unit {
+            Some(unit) if unit != expected => Some((name, unit.as_str())),
+            _ => None,
+        })
+    }
+}
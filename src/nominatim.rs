@@ -0,0 +1,257 @@
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::error::RequestError;
+use crate::geo::{GeoCoordinate, Geocoder, Place, SearchBias, MAX_CANDIDATES};
+use crate::http::HttpClient;
+
+/// Public OpenStreetMap instance, the usage policy allows 1 request per second
+const NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org";
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Geocoder backed by the OpenStreetMap Nominatim API
+#[derive(Debug, Clone)]
+pub struct Nominatim {
+    client: HttpClient,
+    /// Start of the last request, shared by the clones
+    last_request: Arc<Mutex<Option<Instant>>>,
+    min_interval: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    lat: String,
+    lon: String,
+    name: Option<String>,
+    display_name: String,
+    addresstype: Option<String>,
+    address: Option<Address>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseResult {
+    name: Option<String>,
+    display_name: Option<String>,
+    address: Option<Address>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Address {
+    city: Option<String>,
+    town: Option<String>,
+    village: Option<String>,
+    hamlet: Option<String>,
+    state: Option<String>,
+    country: Option<String>,
+    country_code: Option<String>,
+}
+
+impl Nominatim {
+    pub fn new(client: HttpClient) -> Self {
+        Self {
+            client,
+            last_request: Arc::new(Mutex::new(None)),
+            min_interval: MIN_REQUEST_INTERVAL,
+        }
+    }
+
+    /// Wait until the interval since the previous request passes
+    async fn wait_turn(&self) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last_request) = *last_request {
+            tokio::time::sleep_until(last_request + self.min_interval).await;
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    async fn request_api<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, RequestError> {
+        let api_url = format!("{}/{}", NOMINATIM_URL, path);
+
+        self.wait_turn().await;
+        info!("Request API: {} {:?}...", api_url, query);
+
+        let request = self
+            .client
+            .get(api_url)
+            .query(&[("format", "jsonv2")])
+            .query(query);
+        let res = self.client.send(request).await?;
+        let status = res.status();
+
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        Ok(serde_json::from_str(&res.text().await?)?)
+    }
+}
+
+#[async_trait]
+impl Geocoder for Nominatim {
+    fn name(&self) -> &'static str {
+        "nominatim"
+    }
+
+    async fn search(&self, query: &str, bias: &SearchBias) -> Result<Vec<Place>, RequestError> {
+        let limit = MAX_CANDIDATES.to_string();
+        let mut params = vec![("q", query), ("limit", &limit), ("addressdetails", "1")];
+        if let Some(language) = &bias.language {
+            params.push(("accept-language", language));
+        }
+        let viewbox = bias.area.map(|area| {
+            format!(
+                "{},{},{},{}",
+                area.lower.longitude,
+                area.lower.latitude,
+                area.upper.longitude,
+                area.upper.latitude
+            )
+        });
+        if let Some(viewbox) = &viewbox {
+            params.push(("viewbox", viewbox));
+            if bias.restrict_to_area {
+                params.push(("bounded", "1"));
+            }
+        }
+
+        let results: Vec<SearchResult> = self.request_api("search", &params).await?;
+        let places = parse_search_results(results)?;
+        if places.is_empty() {
+            return Err(RequestError::NotFound(query.to_string()));
+        }
+        Ok(places)
+    }
+
+    async fn reverse(&self, point: &GeoCoordinate) -> Result<String, RequestError> {
+        let (lat, lon) = (point.latitude.to_string(), point.longitude.to_string());
+        // Zoom 10 is the level of the cities
+        let result: ReverseResult = self
+            .request_api("reverse", &[("lat", &lat), ("lon", &lon), ("zoom", "10")])
+            .await?;
+        parse_reverse_result(result).ok_or_else(|| {
+            RequestError::NotFound(format!("{:.4}, {:.4}", point.latitude, point.longitude))
+        })
+    }
+}
+
+fn parse_search_results(results: Vec<SearchResult>) -> Result<Vec<Place>, RequestError> {
+    results
+        .into_iter()
+        .map(|result| {
+            let parse_error = || {
+                RequestError::Parse(format!(
+                    "invalid Nominatim point \"{} {}\"",
+                    result.lat, result.lon
+                ))
+            };
+            let lat = result.lat.parse::<f64>().map_err(|_| parse_error())?;
+            let lon = result.lon.parse::<f64>().map_err(|_| parse_error())?;
+
+            let description = result.address.as_ref().and_then(|address| {
+                let parts: Vec<&str> = [&address.state, &address.country]
+                    .into_iter()
+                    .filter_map(|part| part.as_deref())
+                    .collect();
+                (!parts.is_empty()).then(|| parts.join(", "))
+            });
+            let country_code = result
+                .address
+                .as_ref()
+                .and_then(|address| address.country_code.as_ref())
+                .map(|code| code.to_uppercase());
+
+            Ok(Place {
+                name: result
+                    .name
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or_else(|| result.display_name.clone()),
+                description,
+                address: Some(result.display_name),
+                country_code,
+                kind: result.addresstype,
+                coordinates: GeoCoordinate::new(lat, lon),
+            })
+        })
+        .collect()
+}
+
+/// The locality name is preferred to the name of the found object
+fn parse_reverse_result(result: ReverseResult) -> Option<String> {
+    if result.error.is_some() {
+        return None;
+    }
+
+    result
+        .address
+        .and_then(|address| {
+            address
+                .city
+                .or(address.town)
+                .or(address.village)
+                .or(address.hamlet)
+        })
+        .or(result.name.filter(|name| !name.is_empty()))
+        .or(result.display_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_result_is_parsed() {
+        let results: Vec<SearchResult> =
+            serde_json::from_str(include_str!("../tests/fixtures/nominatim/search.json")).unwrap();
+
+        let places = parse_search_results(results).unwrap();
+        assert_eq!(places.len(), 2);
+        assert_eq!(places[0].name, "Oslo");
+        assert_eq!(
+            places[0].coordinates,
+            GeoCoordinate::new(59.9133301, 10.7389701)
+        );
+        assert_eq!(places[0].country_code.as_deref(), Some("NO"));
+        assert_eq!(places[0].kind.as_deref(), Some("city"));
+        assert_eq!(places[1].label(), "Oslo, Ontario, Canada");
+        assert!(parse_search_results(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reverse_result_is_parsed() {
+        let result: ReverseResult =
+            serde_json::from_str(include_str!("../tests/fixtures/nominatim/reverse.json")).unwrap();
+        assert_eq!(parse_reverse_result(result), Some("Oslo".to_string()));
+
+        let result: ReverseResult =
+            serde_json::from_str(r#"{"error":"Unable to geocode"}"#).unwrap();
+        assert_eq!(parse_reverse_result(result), None);
+    }
+
+    #[tokio::test]
+    async fn requests_are_spaced() {
+        let nominatim = Nominatim {
+            min_interval: Duration::from_millis(100),
+            ..Nominatim::new(HttpClient::new(reqwest::Client::new()))
+        };
+        let start = Instant::now();
+        nominatim.wait_turn().await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        // The clones share the last request
+        nominatim.clone().wait_turn().await;
+        nominatim.wait_turn().await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,236 @@
+use log::{error, info};
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use serde::Deserialize;
+
+use crate::error::RequestError;
+use crate::geo::GeoCoordinate;
+use crate::http::HttpClient;
+use crate::weather::{Forecast, ForecastProvider, Forecasts, Hours, NextHours};
+use crate::weather_codes::{SymbolVariant, WeatherCode, WeatherSymbol};
+
+const HOURLY_VARIABLES: &str = "temperature_2m,relative_humidity_2m,dew_point_2m,pressure_msl,\
+cloud_cover,cloud_cover_low,cloud_cover_mid,cloud_cover_high,wind_speed_10m,wind_direction_10m,\
+wind_gusts_10m,precipitation,precipitation_probability,weather_code,is_day,uv_index";
+
+/// Forecast provider backed by the Open-Meteo API
+#[derive(Debug, Clone)]
+pub struct OpenMeteo {
+    client: HttpClient,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    hourly: Hourly,
+}
+
+/// Columns of the hourly values, any value may be missing
+#[derive(Debug, Deserialize)]
+struct Hourly {
+    time: Vec<String>,
+    temperature_2m: Vec<Option<f64>>,
+    relative_humidity_2m: Vec<Option<f64>>,
+    dew_point_2m: Vec<Option<f64>>,
+    pressure_msl: Vec<Option<f64>>,
+    cloud_cover: Vec<Option<f64>>,
+    cloud_cover_low: Vec<Option<f64>>,
+    cloud_cover_mid: Vec<Option<f64>>,
+    cloud_cover_high: Vec<Option<f64>>,
+    wind_speed_10m: Vec<Option<f64>>,
+    wind_direction_10m: Vec<Option<f64>>,
+    wind_gusts_10m: Vec<Option<f64>>,
+    precipitation: Vec<Option<f64>>,
+    precipitation_probability: Vec<Option<f64>>,
+    weather_code: Vec<Option<u8>>,
+    is_day: Vec<Option<u8>>,
+    uv_index: Vec<Option<f64>>,
+}
+
+impl OpenMeteo {
+    pub fn new(client: HttpClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl ForecastProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "openmeteo"
+    }
+
+    async fn get_forecasts(&self, geo_point: &GeoCoordinate) -> Result<Forecasts, RequestError> {
+        let api_url = format!(
+            "https://api.open-meteo.com/v1/forecast?latitude={:.4}&longitude={:.4}\
+            &hourly={}&wind_speed_unit=ms&timezone=GMT&forecast_days=9",
+            geo_point.latitude, geo_point.longitude, HOURLY_VARIABLES
+        );
+
+        info!("Request API: {}...", api_url);
+
+        let res = self.client.send(self.client.get(api_url)).await?;
+        let status = res.status();
+
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        parse_api_response(&res.text().await?)
+    }
+}
+
+/// Map the WMO weather interpretation code to the met.no legend
+fn weather_code(wmo_code: u8) -> WeatherCode {
+    match wmo_code {
+        0 => WeatherCode::ClearSky,
+        1 => WeatherCode::Fair,
+        2 => WeatherCode::PartlyCloudy,
+        3 => WeatherCode::Cloudy,
+        45 | 48 => WeatherCode::Fog,
+        51 | 53 | 61 => WeatherCode::LightRain,
+        55 | 63 => WeatherCode::Rain,
+        65 => WeatherCode::HeavyRain,
+        56 | 57 | 66 => WeatherCode::LightSleet,
+        67 => WeatherCode::Sleet,
+        71 | 77 => WeatherCode::LightSnow,
+        73 => WeatherCode::Snow,
+        75 => WeatherCode::HeavySnow,
+        80 => WeatherCode::LightRainShowers,
+        81 => WeatherCode::RainShowers,
+        82 => WeatherCode::HeavyRainShowers,
+        85 => WeatherCode::LightSnowShowers,
+        86 => WeatherCode::HeavySnowShowers,
+        95 | 96 => WeatherCode::RainAndThunder,
+        99 => WeatherCode::HeavyRainAndThunder,
+        code => WeatherCode::Unknown(format!("wmo_{}", code)),
+    }
+}
+
+fn value(column: &[Option<f64>], index: usize) -> Option<f64> {
+    column.get(index).copied().flatten()
+}
+
+fn required(column: &[Option<f64>], index: usize, name: &str) -> Result<f64, RequestError> {
+    value(column, index)
+        .ok_or_else(|| RequestError::Parse(format!("{}[{}] is missing", name, index)))
+}
+
+/// Open-Meteo sums the precipitation over the hour before the time, so the
+/// values of the next hour describe the hour after the time as met.no does
+fn next_hour(hourly: &Hourly, index: usize) -> Option<NextHours> {
+    let code = weather_code(hourly.weather_code.get(index).copied().flatten()?);
+    let variant = match hourly.is_day.get(index).copied().flatten() {
+        Some(1) => SymbolVariant::Day,
+        _ => SymbolVariant::Night,
+    };
+
+    Some(NextHours {
+        symbol: WeatherSymbol {
+            variant: code.has_variants().then_some(variant),
+            code,
+        },
+        precipitation_amount: value(&hourly.precipitation, index),
+        precipitation_amount_min: None,
+        precipitation_amount_max: None,
+        probability_of_precipitation: value(&hourly.precipitation_probability, index),
+    })
+}
+
+fn parse_api_response(text_response: &str) -> Result<Forecasts, RequestError> {
+    let response: Response = serde_json::from_str(text_response)?;
+    let hourly = &response.hourly;
+
+    let mut forecasts: Forecasts = Default::default();
+    for (index, time) in hourly.time.iter().enumerate() {
+        // The time is in GMT as requested
+        let time: DateTime<FixedOffset> = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+            .map_err(|err| RequestError::Parse(format!("invalid time {}: {}", time, err)))?
+            .and_utc()
+            .fixed_offset();
+        if hourly
+            .temperature_2m
+            .get(index)
+            .copied()
+            .flatten()
+            .is_none()
+        {
+            // The end of the horizon is padded with nulls
+            continue;
+        }
+
+        let mut forecast_by_time = HashMap::new();
+        if let Some(next) = next_hour(hourly, index + 1) {
+            forecast_by_time.insert(Hours::Hour1, next);
+        }
+
+        let forecast = Forecast {
+            air_pressure_at_sea_level: required(&hourly.pressure_msl, index, "pressure_msl")?,
+            air_temperature: required(&hourly.temperature_2m, index, "temperature_2m")?,
+            cloud_area_fraction: required(&hourly.cloud_cover, index, "cloud_cover")?,
+            relative_humidity: required(
+                &hourly.relative_humidity_2m,
+                index,
+                "relative_humidity_2m",
+            )?,
+            wind_from_direction: required(&hourly.wind_direction_10m, index, "wind_direction_10m")?,
+            wind_speed: required(&hourly.wind_speed_10m, index, "wind_speed_10m")?,
+            wind_speed_of_gust: value(&hourly.wind_gusts_10m, index),
+            dew_point_temperature: value(&hourly.dew_point_2m, index),
+            fog_area_fraction: None,
+            ultraviolet_index_clear_sky: value(&hourly.uv_index, index),
+            cloud_area_fraction_high: value(&hourly.cloud_cover_high, index),
+            cloud_area_fraction_medium: value(&hourly.cloud_cover_mid, index),
+            cloud_area_fraction_low: value(&hourly.cloud_cover_low, index),
+            forecast_by_time,
+        };
+        forecasts.insert(time, forecast);
+    }
+
+    Ok(forecasts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_is_parsed() {
+        let forecasts =
+            parse_api_response(include_str!("../tests/fixtures/openmeteo/forecast.json")).unwrap();
+        assert_eq!(forecasts.len(), 4);
+
+        let time = DateTime::parse_from_rfc3339("2023-10-18T12:00:00Z").unwrap();
+        let forecast = &forecasts[&time];
+        assert_eq!(forecast.air_temperature, 9.4);
+        assert_eq!(forecast.air_pressure_at_sea_level, 1004.2);
+        assert_eq!(forecast.wind_speed, 7.1);
+        assert_eq!(forecast.wind_speed_of_gust, Some(13.6));
+        assert_eq!(forecast.cloud_area_fraction_low, Some(62.0));
+
+        let next = forecast.get_next_hours(Hours::Hour1).unwrap();
+        assert_eq!(next.symbol.to_string(), "lightrain");
+        assert_eq!(next.precipitation_amount, Some(0.6));
+        assert_eq!(next.probability_of_precipitation, Some(70.0));
+    }
+
+    #[test]
+    fn last_hour_has_no_next_hour() {
+        let forecasts =
+            parse_api_response(include_str!("../tests/fixtures/openmeteo/forecast.json")).unwrap();
+
+        let time = DateTime::parse_from_rfc3339("2023-10-18T14:00:00Z").unwrap();
+        assert!(forecasts[&time].get_next_hours(Hours::Hour1).is_none());
+        let time = DateTime::parse_from_rfc3339("2023-10-18T11:00:00Z").unwrap();
+        let next = forecasts[&time].get_next_hours(Hours::Hour1).unwrap();
+        assert_eq!(next.symbol.to_string(), "cloudy");
+    }
+
+    #[test]
+    fn wmo_codes_are_mapped() {
+        assert_eq!(weather_code(0), WeatherCode::ClearSky);
+        assert_eq!(weather_code(95), WeatherCode::RainAndThunder);
+        assert_eq!(weather_code(42), WeatherCode::Unknown("wmo_42".to_string()));
+    }
+}
@@ -0,0 +1,272 @@
+use log::{error, info};
+use std::collections::HashMap;
+use std::env;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::Deserialize;
+
+use crate::error::RequestError;
+use crate::geo::GeoCoordinate;
+use crate::http::HttpClient;
+use crate::weather::{Forecast, ForecastProvider, Forecasts, Hours, NextHours};
+use crate::weather_codes::{SymbolVariant, WeatherCode, WeatherSymbol};
+
+/// Forecast provider backed by the OpenWeatherMap 5 day / 3 hour forecast API
+#[derive(Debug, Clone)]
+pub struct OpenWeatherMap {
+    client: HttpClient,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    list: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Entry {
+    dt: i64,
+    main: Main,
+    #[serde(default)]
+    weather: Vec<Condition>,
+    clouds: Clouds,
+    wind: Wind,
+    /// Probability of precipitation from 0 to 1
+    pop: Option<f64>,
+    rain: Option<Volume>,
+    snow: Option<Volume>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Main {
+    temp: f64,
+    pressure: f64,
+    sea_level: Option<f64>,
+    humidity: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Condition {
+    id: u16,
+    icon: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Clouds {
+    all: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Wind {
+    speed: f64,
+    deg: f64,
+    gust: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Volume {
+    #[serde(rename = "3h")]
+    three_hours: Option<f64>,
+}
+
+impl OpenWeatherMap {
+    pub fn new(client: HttpClient, api_key: String) -> Self {
+        Self { client, api_key }
+    }
+
+    pub fn from_env(client: HttpClient) -> Result<Self, RequestError> {
+        let api_key = env::var("OWM_API_KEY")
+            .map_err(|_| RequestError::Config("OWM_API_KEY must be set up".to_string()))?;
+        Ok(Self::new(client, api_key))
+    }
+}
+
+#[async_trait]
+impl ForecastProvider for OpenWeatherMap {
+    fn name(&self) -> &'static str {
+        "openweathermap"
+    }
+
+    async fn get_forecasts(&self, geo_point: &GeoCoordinate) -> Result<Forecasts, RequestError> {
+        let api_url = format!(
+            "https://api.openweathermap.org/data/2.5/forecast?lat={:.4}&lon={:.4}&units=metric&appid={}",
+            geo_point.latitude, geo_point.longitude, self.api_key
+        );
+
+        info!(
+            "Request API: OpenWeatherMap forecast for {:?}...",
+            geo_point
+        );
+
+        let res = self.client.send(self.client.get(api_url)).await?;
+        let status = res.status();
+
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        parse_api_response(&res.text().await?)
+    }
+}
+
+/// Map the OpenWeatherMap condition id to the met.no legend
+fn weather_code(id: u16) -> WeatherCode {
+    match id {
+        200 | 210 | 230 => WeatherCode::LightRainAndThunder,
+        202 | 212 | 232 => WeatherCode::HeavyRainAndThunder,
+        200..=299 => WeatherCode::RainAndThunder,
+        300..=399 | 500 => WeatherCode::LightRain,
+        501 => WeatherCode::Rain,
+        502..=504 => WeatherCode::HeavyRain,
+        511 | 611..=613 | 616 => WeatherCode::Sleet,
+        615 => WeatherCode::LightSleet,
+        520 => WeatherCode::LightRainShowers,
+        521 => WeatherCode::RainShowers,
+        522 | 531 => WeatherCode::HeavyRainShowers,
+        600 => WeatherCode::LightSnow,
+        601 => WeatherCode::Snow,
+        602 => WeatherCode::HeavySnow,
+        620 => WeatherCode::LightSnowShowers,
+        621 => WeatherCode::SnowShowers,
+        622 => WeatherCode::HeavySnowShowers,
+        700..=799 => WeatherCode::Fog,
+        800 => WeatherCode::ClearSky,
+        801 => WeatherCode::Fair,
+        802 => WeatherCode::PartlyCloudy,
+        803 | 804 => WeatherCode::Cloudy,
+        id => WeatherCode::Unknown(format!("owm_{}", id)),
+    }
+}
+
+/// OpenWeatherMap gives the precipitation of the 3 hours before the time,
+/// so the next entry describes the 3 hours after the time as met.no does
+fn next_hours(entry: &Entry) -> Option<NextHours> {
+    let condition = entry.weather.first()?;
+    let code = weather_code(condition.id);
+    let variant = if condition.icon.ends_with('n') {
+        SymbolVariant::Night
+    } else {
+        SymbolVariant::Day
+    };
+    let volume = |volume: &Option<Volume>| volume.as_ref().and_then(|volume| volume.three_hours);
+    // Dry periods have neither rain nor snow
+    let precipitation_amount =
+        volume(&entry.rain).unwrap_or(0.0) + volume(&entry.snow).unwrap_or(0.0);
+
+    Some(NextHours {
+        symbol: WeatherSymbol {
+            variant: code.has_variants().then_some(variant),
+            code,
+        },
+        precipitation_amount: Some(precipitation_amount),
+        precipitation_amount_min: None,
+        precipitation_amount_max: None,
+        probability_of_precipitation: entry.pop.map(|pop| (pop * 100.0).round()),
+    })
+}
+
+fn parse_api_response(text_response: &str) -> Result<Forecasts, RequestError> {
+    let response: Response = serde_json::from_str(text_response)?;
+
+    let mut forecasts: Forecasts = Default::default();
+    for (index, entry) in response.list.iter().enumerate() {
+        let time: DateTime<FixedOffset> = Utc
+            .timestamp_opt(entry.dt, 0)
+            .single()
+            .ok_or_else(|| RequestError::Parse(format!("invalid time {}", entry.dt)))?
+            .fixed_offset();
+
+        let mut forecast_by_time = HashMap::new();
+        if let Some(next) = response.list.get(index + 1).and_then(next_hours) {
+            forecast_by_time.insert(Hours::Hour3, next);
+        }
+
+        let forecast = Forecast {
+            air_pressure_at_sea_level: entry.main.sea_level.unwrap_or(entry.main.pressure),
+            air_temperature: entry.main.temp,
+            cloud_area_fraction: entry.clouds.all,
+            relative_humidity: entry.main.humidity,
+            wind_from_direction: entry.wind.deg,
+            wind_speed: entry.wind.speed,
+            wind_speed_of_gust: entry.wind.gust,
+            dew_point_temperature: None,
+            fog_area_fraction: None,
+            ultraviolet_index_clear_sky: None,
+            cloud_area_fraction_high: None,
+            cloud_area_fraction_medium: None,
+            cloud_area_fraction_low: None,
+            forecast_by_time,
+        };
+        forecasts.insert(time, forecast);
+    }
+
+    Ok(forecasts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::Weather;
+
+    #[test]
+    fn response_is_parsed() {
+        let forecasts = parse_api_response(include_str!(
+            "../tests/fixtures/openweathermap/forecast.json"
+        ))
+        .unwrap();
+        assert_eq!(forecasts.len(), 3);
+
+        let time = DateTime::parse_from_rfc3339("2023-10-18T12:00:00Z").unwrap();
+        let forecast = &forecasts[&time];
+        assert_eq!(forecast.air_temperature, 9.38);
+        assert_eq!(forecast.air_pressure_at_sea_level, 1004.0);
+        assert_eq!(forecast.relative_humidity, 80.0);
+        assert_eq!(forecast.wind_from_direction, 210.0);
+        assert_eq!(forecast.wind_speed_of_gust, Some(13.52));
+
+        let next = forecast.get_next_hours(Hours::Hour3).unwrap();
+        assert_eq!(next.symbol.to_string(), "lightrain");
+        assert_eq!(next.precipitation_amount, Some(1.79));
+        assert_eq!(next.probability_of_precipitation, Some(86.0));
+
+        let time = DateTime::parse_from_rfc3339("2023-10-18T18:00:00Z").unwrap();
+        assert!(forecasts[&time].get_next_hours(Hours::Hour3).is_none());
+    }
+
+    #[test]
+    fn current_conditions_are_taken_before_the_first_step() {
+        let weather = Weather {
+            forecasts: parse_api_response(include_str!(
+                "../tests/fixtures/openweathermap/forecast.json"
+            ))
+            .unwrap(),
+            coordinates: GeoCoordinate::new(55.75, 37.62),
+        };
+
+        // The list starts at the next 3 hour step after now
+        let now = DateTime::parse_from_rfc3339("2023-10-18T10:20:00+01:00").unwrap();
+        assert_eq!(weather.get_forecast_at(&now).unwrap().air_temperature, 9.38);
+        let time = DateTime::parse_from_rfc3339("2023-10-18T13:30:00Z").unwrap();
+        let forecast = weather.get_forecast_at(&time).unwrap();
+        assert!(forecast.air_temperature != 9.38);
+
+        let too_early = DateTime::parse_from_rfc3339("2023-10-18T08:59:00Z").unwrap();
+        assert!(matches!(
+            weather.get_forecast_at(&too_early),
+            Err(RequestError::OutOfHorizon { .. })
+        ));
+    }
+
+    #[test]
+    fn condition_ids_are_mapped() {
+        assert_eq!(weather_code(800), WeatherCode::ClearSky);
+        assert_eq!(weather_code(741), WeatherCode::Fog);
+        assert_eq!(weather_code(211), WeatherCode::RainAndThunder);
+        assert_eq!(
+            weather_code(900),
+            WeatherCode::Unknown("owm_900".to_string())
+        );
+    }
+}
@@ -0,0 +1,612 @@
+use log::{error, info};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Duration as ChronoDuration, FixedOffset, NaiveTime};
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+
+use weather_bot::geo::GeoCoordinate;
+use weather_bot::settings::{SettingsKey, Units, UserSettings};
+use weather_bot::storage::{AsyncStorage, Storage};
+use weather_bot::timezone::TimezoneFinder;
+use weather_bot::weather::{FailoverProvider, Forecast, Forecasts, Weather};
+
+pub type RuleStorage = Arc<dyn Storage<RuleKey, RuleState>>;
+
+/// How often the rules are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Matches closer than this to the notified event belong to the same event
+const EVENT_GAP_HOURS: i64 = 6;
+/// Period checked when the rule has no day
+const SOON_HOURS: i64 = 24;
+
+pub const RULE_HELP: &str = "Please enter the rule. Examples:
+/alert temp < -10
+/alert wind > 15 tomorrow
+/alert rain today
+Values: temp, wind, gust, humidity, pressure, rain (mm/h) in your units";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    Temperature,
+    Wind,
+    Gust,
+    Humidity,
+    Pressure,
+    /// Precipitation rate in mm per hour
+    Precipitation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    Less,
+    Greater,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Period {
+    /// The next 24 hours
+    Soon,
+    Today,
+    Tomorrow,
+}
+
+/// User defined trigger, e.g. `temp < -10 tomorrow`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub metric: Metric,
+    pub comparison: Comparison,
+    /// Threshold in the units of the user
+    pub value: f64,
+    pub period: Period,
+}
+
+/// A user has the rules per chat, the rule text is its identity
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RuleKey {
+    pub chat_id: i64,
+    pub user_id: u64,
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleState {
+    pub rule: Rule,
+    /// Units of the user when the rule was added
+    pub units: Units,
+    /// End of the last notified event
+    pub notified_until: Option<DateTime<FixedOffset>>,
+}
+
+/// Forecast hours matching the rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub start: DateTime<FixedOffset>,
+    pub end: DateTime<FixedOffset>,
+    /// The lowest or the highest value depending on the comparison
+    pub extreme: f64,
+}
+
+impl Metric {
+    fn name(&self) -> &'static str {
+        match self {
+            Metric::Temperature => "temp",
+            Metric::Wind => "wind",
+            Metric::Gust => "gust",
+            Metric::Humidity => "humidity",
+            Metric::Pressure => "pressure",
+            Metric::Precipitation => "rain",
+        }
+    }
+
+    fn symbol(&self, units: &Units) -> &'static str {
+        match self {
+            Metric::Temperature => units.temperature.symbol(),
+            Metric::Wind | Metric::Gust => units.wind_speed.symbol(),
+            Metric::Humidity => "%",
+            Metric::Pressure => units.pressure.symbol(),
+            Metric::Precipitation => "mm/h",
+        }
+    }
+
+    /// Value of the forecast in the units of the user
+    fn value(&self, forecast: &Forecast, units: &Units) -> Option<f64> {
+        match self {
+            Metric::Temperature => Some(units.temperature.convert(forecast.air_temperature)),
+            Metric::Wind => Some(units.wind_speed.convert(forecast.wind_speed)),
+            Metric::Gust => forecast
+                .wind_speed_of_gust
+                .map(|gust| units.wind_speed.convert(gust)),
+            Metric::Humidity => Some(forecast.relative_humidity),
+            Metric::Pressure => Some(units.pressure.convert(forecast.air_pressure_at_sea_level)),
+            Metric::Precipitation => forecast.nearest_period().and_then(|(hours, next)| {
+                next.precipitation_amount
+                    .map(|amount| amount / hours.duration() as f64)
+            }),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "temp" | "temperature" => Ok(Metric::Temperature),
+            "wind" => Ok(Metric::Wind),
+            "gust" | "gusts" => Ok(Metric::Gust),
+            "humidity" => Ok(Metric::Humidity),
+            "pressure" => Ok(Metric::Pressure),
+            "rain" | "precipitation" => Ok(Metric::Precipitation),
+            _ => Err(format!("Unknown value \"{}\"\n\n{}", s, RULE_HELP)),
+        }
+    }
+}
+
+impl Rule {
+    /// `rain` without a threshold means any precipitation
+    fn is_any_rain(&self) -> bool {
+        self.metric == Metric::Precipitation
+            && self.comparison == Comparison::Greater
+            && self.value == 0.0
+    }
+
+    fn matches(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Less => value < self.value,
+            Comparison::Greater => value > self.value,
+        }
+    }
+
+    /// Time range of the period in the timezone of `now`
+    fn window(
+        &self,
+        now: &DateTime<FixedOffset>,
+    ) -> (DateTime<FixedOffset>, DateTime<FixedOffset>) {
+        let start_of_day = |days: i64| {
+            let date = now.date_naive() + ChronoDuration::days(days);
+            date.and_time(NaiveTime::MIN)
+                .and_local_timezone(*now.offset())
+                .unwrap()
+        };
+        // The current hour is still going on
+        let from = *now - ChronoDuration::hours(1);
+
+        match self.period {
+            Period::Soon => (from, *now + ChronoDuration::hours(SOON_HOURS)),
+            Period::Today => (from, start_of_day(1)),
+            Period::Tomorrow => (start_of_day(1), start_of_day(2)),
+        }
+    }
+
+    /// Find the forecast hours of the period matching the rule
+    pub fn find_event(
+        &self,
+        forecasts: &Forecasts,
+        units: &Units,
+        now: &DateTime<FixedOffset>,
+    ) -> Option<Event> {
+        let (from, till) = self.window(now);
+        let mut times: Vec<&DateTime<FixedOffset>> = forecasts
+            .keys()
+            .filter(|time| **time > from && **time < till)
+            .collect();
+        times.sort();
+
+        let mut event: Option<Event> = None;
+        for key in times {
+            // The event is shown in the local time of `now`
+            let time = key.with_timezone(now.offset());
+            let value = match self.metric.value(&forecasts[key], units) {
+                Some(value) if self.matches(value) => value,
+                _ => continue,
+            };
+            match &mut event {
+                Some(event) => {
+                    event.end = time;
+                    event.extreme = match self.comparison {
+                        Comparison::Less => event.extreme.min(value),
+                        Comparison::Greater => event.extreme.max(value),
+                    };
+                }
+                None => {
+                    event = Some(Event {
+                        start: time,
+                        end: time,
+                        extreme: value,
+                    })
+                }
+            }
+        }
+
+        event
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parse `<value> <|> <number> [today|tomorrow]` or `rain [today|tomorrow]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words: Vec<&str> = s.split_whitespace().collect();
+        let period = match words.last().map(|word| word.to_lowercase()).as_deref() {
+            Some("today") => Period::Today,
+            Some("tomorrow") => Period::Tomorrow,
+            _ => Period::Soon,
+        };
+        if period != Period::Soon {
+            words.pop();
+        }
+        if words.is_empty() {
+            return Err(RULE_HELP.to_string());
+        }
+
+        let metric: Metric = words[0].parse()?;
+        let (comparison, value) = match words[1..] {
+            [] if metric == Metric::Precipitation => (Comparison::Greater, 0.0),
+            [comparison, value] => {
+                let comparison = match comparison {
+                    "<" => Comparison::Less,
+                    ">" => Comparison::Greater,
+                    _ => return Err(RULE_HELP.to_string()),
+                };
+                // `nan` and `inf` are parsed too, but never match
+                let value = value
+                    .replace(',', ".")
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| RULE_HELP.to_string())?;
+                (comparison, value)
+            }
+            _ => return Err(RULE_HELP.to_string()),
+        };
+
+        Ok(Rule {
+            metric,
+            comparison,
+            value,
+            period,
+        })
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_any_rain() {
+            write!(f, "rain")?;
+        } else {
+            let comparison = match self.comparison {
+                Comparison::Less => "<",
+                Comparison::Greater => ">",
+            };
+            write!(f, "{} {} {}", self.metric.name(), comparison, self.value)?;
+        }
+
+        match self.period {
+            Period::Soon => Ok(()),
+            Period::Today => write!(f, " today"),
+            Period::Tomorrow => write!(f, " tomorrow"),
+        }
+    }
+}
+
+impl RuleState {
+    pub fn new(rule: Rule, units: Units) -> Self {
+        Self {
+            rule,
+            units,
+            notified_until: None,
+        }
+    }
+
+    /// Remember the event, returns `false` if it was already notified
+    pub fn register_event(&mut self, event: &Event) -> bool {
+        let is_new = self.notified_until.is_none_or(|until| {
+            event.start.signed_duration_since(until).num_hours() >= EVENT_GAP_HOURS
+        });
+
+        self.notified_until = match self.notified_until {
+            Some(until) if !is_new => Some(until.max(event.end)),
+            _ => Some(event.end),
+        };
+        is_new
+    }
+}
+
+pub fn display_rules_telegram(rules: &[(RuleKey, RuleState)]) -> String {
+    if rules.is_empty() {
+        return "You have no alert rules in this chat".to_string();
+    }
+
+    rules
+        .iter()
+        .map(|(key, _)| key.rule.clone())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn display_event_telegram(rule: &Rule, units: &Units, place: &str, event: &Event) -> String {
+    let time_format = "%d %b %H:%M";
+    let mut message = format!(
+        "🔔 {} in {}\nFrom {} till {}",
+        rule,
+        place,
+        event.start.format(time_format),
+        event.end.format(time_format)
+    );
+    if !rule.is_any_rain() {
+        let direction = match rule.comparison {
+            Comparison::Less => "down to",
+            Comparison::Greater => "up to",
+        };
+        message.push_str(&format!(
+            ", {} {:.1} {}",
+            direction,
+            event.extreme,
+            rule.metric.symbol(units)
+        ));
+    }
+    message
+}
+
+async fn check_rules(
+    bot: &Bot,
+    provider: &FailoverProvider,
+    timezones: &TimezoneFinder,
+    settings_storage: &Arc<dyn Storage<SettingsKey, UserSettings>>,
+    rules: &RuleStorage,
+) {
+    let all = match rules.all() {
+        Ok(all) => all,
+        Err(err) => {
+            error!("Can't load the alert rules: {}", err);
+            return;
+        }
+    };
+
+    // Users sharing the home location share the forecast request
+    let mut forecasts: Vec<(GeoCoordinate, Forecasts)> = Vec::new();
+    for (key, mut state) in all {
+        let settings_key = SettingsKey {
+            chat_id: key.chat_id,
+            user_id: key.user_id,
+        };
+        let settings = match settings_storage.get(&settings_key) {
+            Ok(settings) => settings.unwrap_or_default(),
+            Err(err) => {
+                error!("Can't load the settings: {}", err);
+                continue;
+            }
+        };
+        let Some(home) = settings.home.as_ref() else {
+            continue;
+        };
+
+        if !forecasts
+            .iter()
+            .any(|(coordinates, _)| *coordinates == home.coordinates)
+        {
+            let provider = provider.preferring(settings.provider.as_deref());
+            match Weather::new(&provider, home.coordinates).await {
+                Ok(weather) => forecasts.push((home.coordinates, weather.forecasts)),
+                Err(err) => {
+                    error!("Can't get the forecast for {}: {}", home.name, err);
+                    continue;
+                }
+            }
+        }
+        let (_, home_forecasts) = forecasts
+            .iter()
+            .find(|(coordinates, _)| *coordinates == home.coordinates)
+            .unwrap();
+
+        // Today and tomorrow are the days at home
+        let now = timezones.now_at(&home.coordinates);
+        let Some(event) = state.rule.find_event(home_forecasts, &state.units, &now) else {
+            continue;
+        };
+        if !state.register_event(&event) {
+            // Keep the extended end of the ongoing event
+            if let Err(err) = rules.set_async(key, state).await {
+                error!("Can't save the alert rule: {}", err);
+            }
+            continue;
+        }
+
+        info!("Send the alert \"{}\" to {}", key.rule, key.chat_id);
+        let text = display_event_telegram(&state.rule, &state.units, &home.name, &event);
+        if let Err(err) = bot.send_message(ChatId(key.chat_id), text).await {
+            error!("Can't send the alert to {}: {}", key.chat_id, err);
+        }
+        if let Err(err) = rules.set_async(key, state).await {
+            error!("Can't save the alert rule: {}", err);
+        }
+    }
+}
+
+/// Check the alert rules against the forecast, never returns
+pub async fn run(
+    bot: Bot,
+    provider: Arc<FailoverProvider>,
+    timezones: Arc<TimezoneFinder>,
+    settings_storage: Arc<dyn Storage<SettingsKey, UserSettings>>,
+    rules: RuleStorage,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        check_rules(&bot, &provider, &timezones, &settings_storage, &rules).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use weather_bot::settings::{PressureUnit, TemperatureUnit};
+
+    fn time(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    fn forecasts() -> Forecasts {
+        weather_bot::metno::parse_response(include_str!("../tests/fixtures/response.json")).unwrap()
+    }
+
+    fn find_event(rule: &str, units: &Units) -> Option<Event> {
+        let rule: Rule = rule.parse().unwrap();
+        // The morning of the second day of the fixture in Moscow
+        rule.find_event(&forecasts(), units, &time("2023-07-16T10:30:00+03:00"))
+    }
+
+    #[test]
+    fn rules_are_parsed() {
+        let rule: Rule = "temp < -10".parse().unwrap();
+        assert_eq!(
+            rule,
+            Rule {
+                metric: Metric::Temperature,
+                comparison: Comparison::Less,
+                value: -10.0,
+                period: Period::Soon,
+            }
+        );
+
+        let rule: Rule = "Wind > 15 tomorrow".parse().unwrap();
+        assert_eq!(rule.metric, Metric::Wind);
+        assert_eq!(rule.comparison, Comparison::Greater);
+        assert_eq!(rule.period, Period::Tomorrow);
+
+        let rule: Rule = "rain today".parse().unwrap();
+        assert!(rule.is_any_rain());
+        assert_eq!(rule.period, Period::Today);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for rule in [
+            "",
+            "temp",
+            "temp = 5",
+            "temp < cold",
+            "snow > 1",
+            "wind > 1 2",
+            "today",
+            "tomorrow",
+            "temp < nan",
+            "wind > inf",
+            "temp < -infinity",
+        ] {
+            assert!(rule.parse::<Rule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn rules_display_as_parsed() {
+        for rule in [
+            "temp < -10",
+            "wind > 15.5 tomorrow",
+            "rain today",
+            "rain > 2",
+        ] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+    }
+
+    #[test]
+    fn windows_are_the_local_days() {
+        let now = time("2023-07-16T10:30:00+03:00");
+        let window = |rule: &str| rule.parse::<Rule>().unwrap().window(&now);
+
+        assert_eq!(
+            window("rain"),
+            (
+                time("2023-07-16T09:30:00+03:00"),
+                time("2023-07-17T10:30:00+03:00")
+            )
+        );
+        assert_eq!(
+            window("rain today"),
+            (
+                time("2023-07-16T09:30:00+03:00"),
+                time("2023-07-17T00:00:00+03:00")
+            )
+        );
+        assert_eq!(
+            window("rain tomorrow"),
+            (
+                time("2023-07-17T00:00:00+03:00"),
+                time("2023-07-18T00:00:00+03:00")
+            )
+        );
+    }
+
+    #[test]
+    fn events_are_found_in_the_window() {
+        let units = Units::default();
+
+        let event = find_event("temp > 14 today", &units).unwrap();
+        assert_eq!(event.start, time("2023-07-16T10:00:00+03:00"));
+        assert_eq!(event.end, time("2023-07-16T14:00:00+03:00"));
+        assert_eq!(event.extreme, 14.8);
+
+        let event = find_event("temp > 14 tomorrow", &units).unwrap();
+        assert_eq!(event.start, time("2023-07-17T21:00:00+03:00"));
+        assert_eq!(event.end, event.start);
+        assert_eq!(event.extreme, 15.0);
+
+        assert_eq!(find_event("temp < 10 today", &units), None);
+    }
+
+    #[test]
+    fn rain_rate_of_the_6_hour_steps_is_hourly() {
+        // 2.1 mm in the 6 hours after the first 6-hour step, the hourly steps are dry
+        let event = find_event("rain > 0.3 tomorrow", &Units::default()).unwrap();
+        assert_eq!(event.start, time("2023-07-17T15:00:00+03:00"));
+        assert_eq!(event.end, event.start);
+        assert!((event.extreme - 0.35).abs() < 1e-9);
+
+        assert_eq!(find_event("rain > 0.4 tomorrow", &Units::default()), None);
+        assert!(find_event("rain today", &Units::default()).is_some());
+    }
+
+    #[test]
+    fn values_are_compared_in_the_units_of_the_user() {
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            pressure: PressureUnit::MmHg,
+            ..Default::default()
+        };
+
+        // 15 ℃
+        let event = find_event("temp > 58 tomorrow", &units).unwrap();
+        assert!((event.extreme - 59.0).abs() < 1e-9);
+        assert_eq!(find_event("temp > 58 tomorrow", &Units::default()), None);
+
+        // 998.7 hPa
+        let event = find_event("pressure < 749.5 today", &units).unwrap();
+        assert!((event.extreme - 749.08).abs() < 0.01);
+    }
+
+    #[test]
+    fn event_is_notified_once() {
+        let rule: Rule = "wind > 15".parse().unwrap();
+        let mut state = RuleState::new(rule, Units::default());
+        let event = |start, end| Event {
+            start: time(start),
+            end: time(end),
+            extreme: 20.0,
+        };
+
+        assert!(state.register_event(&event("2023-10-18T12:00:00Z", "2023-10-18T18:00:00Z")));
+        // The storm goes on
+        assert!(!state.register_event(&event("2023-10-18T13:00:00Z", "2023-10-18T21:00:00Z")));
+        assert!(!state.register_event(&event("2023-10-18T22:00:00Z", "2023-10-19T02:00:00Z")));
+        assert_eq!(state.notified_until, Some(time("2023-10-19T02:00:00Z")));
+        // A new storm after a calm
+        assert!(state.register_event(&event("2023-10-19T12:00:00Z", "2023-10-19T15:00:00Z")));
+    }
+}
@@ -0,0 +1,328 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::geo::{GeoCoordinate, SearchArea, SearchBias};
+
+/// Settings are kept per user in every chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SettingsKey {
+    pub chat_id: i64,
+    pub user_id: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserSettings {
+    pub units: Units,
+    pub language: Language,
+    pub home: Option<HomeLocation>,
+    /// IANA timezone name of the new daily forecasts, e.g. `Europe/Moscow`.
+    /// The forecasts themselves are shown in the time of the place
+    pub timezone: Option<String>,
+    /// Preferred forecast provider, the configured order is used if not set
+    pub provider: Option<String>,
+    /// Missing in the settings saved before it, they search anywhere
+    #[serde(default)]
+    pub search_scope: SearchScope,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HomeLocation {
+    pub name: String,
+    pub coordinates: GeoCoordinate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub pressure: PressureUnit,
+    pub wind_speed: WindSpeedUnit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PressureUnit {
+    #[default]
+    MmHg,
+    HPa,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindSpeedUnit {
+    #[default]
+    MetersPerSecond,
+    KilometersPerHour,
+    MilesPerHour,
+}
+
+/// Where the cities are searched, the home region only needs the home city
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchScope {
+    #[default]
+    Anywhere,
+    Home,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    En,
+    Ru,
+}
+
+/// Size of the home region preferred by the geocoders, about 500 km
+const HOME_REGION_SPAN_DEGREES: f64 = 5.0;
+
+impl UserSettings {
+    /// The geocoders prefer the names in the language of the user and the
+    /// places around the home, or search only there
+    pub fn search_bias(&self) -> SearchBias {
+        SearchBias {
+            language: Some(self.language.code().to_string()),
+            area: self.home.as_ref().map(|home| {
+                SearchArea::around(
+                    home.coordinates,
+                    HOME_REGION_SPAN_DEGREES,
+                    HOME_REGION_SPAN_DEGREES,
+                )
+            }),
+            restrict_to_area: self.search_scope == SearchScope::Home,
+        }
+    }
+}
+
+impl TemperatureUnit {
+    /// Convert from degrees Celsius which met.no uses
+    pub fn convert(&self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "℃",
+            TemperatureUnit::Fahrenheit => "℉",
+        }
+    }
+}
+
+impl PressureUnit {
+    /// Convert from hectopascals which met.no uses
+    pub fn convert(&self, hpa: f64) -> f64 {
+        match self {
+            PressureUnit::MmHg => hpa * 0.750062,
+            PressureUnit::HPa => hpa,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            PressureUnit::MmHg => "mmHg",
+            PressureUnit::HPa => "hPa",
+        }
+    }
+}
+
+impl WindSpeedUnit {
+    /// Convert from meters per second which met.no uses
+    pub fn convert(&self, meters_per_second: f64) -> f64 {
+        match self {
+            WindSpeedUnit::MetersPerSecond => meters_per_second,
+            WindSpeedUnit::KilometersPerHour => meters_per_second * 3.6,
+            WindSpeedUnit::MilesPerHour => meters_per_second * 2.236936,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            WindSpeedUnit::MetersPerSecond => "m/s",
+            WindSpeedUnit::KilometersPerHour => "km/h",
+            WindSpeedUnit::MilesPerHour => "mph",
+        }
+    }
+}
+
+impl SearchScope {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SearchScope::Anywhere => "anywhere",
+            SearchScope::Home => "home",
+        }
+    }
+}
+
+impl Language {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Ru => "ru",
+        }
+    }
+}
+
+impl FromStr for TemperatureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "c" | "celsius" => Ok(TemperatureUnit::Celsius),
+            "f" | "fahrenheit" => Ok(TemperatureUnit::Fahrenheit),
+            _ => Err("Temperature unit must be one of: c, f".to_string()),
+        }
+    }
+}
+
+impl FromStr for PressureUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mmhg" => Ok(PressureUnit::MmHg),
+            "hpa" => Ok(PressureUnit::HPa),
+            _ => Err("Pressure unit must be one of: mmhg, hpa".to_string()),
+        }
+    }
+}
+
+impl FromStr for WindSpeedUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ms" | "m/s" => Ok(WindSpeedUnit::MetersPerSecond),
+            "kmh" | "km/h" => Ok(WindSpeedUnit::KilometersPerHour),
+            "mph" => Ok(WindSpeedUnit::MilesPerHour),
+            _ => Err("Wind speed unit must be one of: ms, kmh, mph".to_string()),
+        }
+    }
+}
+
+impl FromStr for SearchScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "anywhere" => Ok(SearchScope::Anywhere),
+            "home" => Ok(SearchScope::Home),
+            _ => Err("Search scope must be one of: anywhere, home".to_string()),
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "en" => Ok(Language::En),
+            "ru" => Ok(Language::Ru),
+            _ => Err("Language must be one of: en, ru".to_string()),
+        }
+    }
+}
+
+/// Current time in the timezone
+pub fn now_in_timezone(timezone: &Tz) -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(timezone).fixed_offset()
+}
+
+/// Validate the IANA timezone name
+pub fn parse_timezone(name: &str) -> Result<String, String> {
+    Tz::from_str(name)
+        .map(|tz| tz.name().to_string())
+        .map_err(|_| format!("Unknown timezone \"{}\". Example: Europe/Moscow", name))
+}
+
+impl std::fmt::Display for UserSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Temperature: {}\nPressure: {}\nWind speed: {}\nLanguage: {}\nHome: {}\nTimezone: {}\nProvider: {}\nSearch: {}",
+            self.units.temperature.symbol(),
+            self.units.pressure.symbol(),
+            self.units.wind_speed.symbol(),
+            self.language.code(),
+            self.home
+                .as_ref()
+                .map(|home| home.name.as_str())
+                .unwrap_or("not set"),
+            self.timezone.as_deref().unwrap_or("not set"),
+            self.provider.as_deref().unwrap_or("not set"),
+            self.search_scope.code(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn units_are_converted() {
+        assert_close(TemperatureUnit::Celsius.convert(-5.0), -5.0);
+        assert_close(TemperatureUnit::Fahrenheit.convert(-40.0), -40.0);
+        assert_close(TemperatureUnit::Fahrenheit.convert(100.0), 212.0);
+
+        // The standard atmosphere
+        assert_close(PressureUnit::MmHg.convert(1013.25), 760.0);
+        assert_close(PressureUnit::HPa.convert(1013.25), 1013.25);
+
+        assert_close(WindSpeedUnit::MetersPerSecond.convert(10.0), 10.0);
+        assert_close(WindSpeedUnit::KilometersPerHour.convert(10.0), 36.0);
+        assert_close(WindSpeedUnit::MilesPerHour.convert(10.0), 22.37);
+    }
+
+    #[test]
+    fn units_are_parsed() {
+        assert_eq!("F".parse(), Ok(TemperatureUnit::Fahrenheit));
+        assert_eq!("mmHg".parse(), Ok(PressureUnit::MmHg));
+        assert_eq!("km/h".parse(), Ok(WindSpeedUnit::KilometersPerHour));
+        assert!("kelvin".parse::<TemperatureUnit>().is_err());
+        assert_eq!(Units::default().pressure, PressureUnit::MmHg);
+    }
+
+    #[test]
+    fn search_is_restricted_to_the_home_region() {
+        let mut settings = UserSettings {
+            home: Some(HomeLocation {
+                name: "Moscow".to_string(),
+                coordinates: GeoCoordinate::new(55.75, 37.62),
+            }),
+            ..Default::default()
+        };
+        assert!(!settings.search_bias().restrict_to_area);
+
+        settings.search_scope = "home".parse().unwrap();
+        let bias = settings.search_bias();
+        assert!(bias.restrict_to_area);
+        assert!(bias
+            .area
+            .unwrap()
+            .contains(&GeoCoordinate::new(55.75, 37.62)));
+
+        // Saved before the scope
+        let saved = r#"{"units":{"temperature":"Celsius","pressure":"MmHg","wind_speed":"MetersPerSecond"},
+            "language":"En","home":null,"timezone":null,"provider":null}"#;
+        let saved: UserSettings = serde_json::from_str(saved).unwrap();
+        assert_eq!(saved.search_scope, SearchScope::Anywhere);
+    }
+}
@@ -0,0 +1,269 @@
+use log::{error, info};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::StorageError;
+
+/// Key-value storage of the bot state
+pub trait Storage<K, V>: Send + Sync {
+    fn get(&self, key: &K) -> Result<Option<V>, StorageError>;
+    fn set(&self, key: K, value: V) -> Result<(), StorageError>;
+    fn remove(&self, key: &K) -> Result<Option<V>, StorageError>;
+    fn all(&self) -> Result<Vec<(K, V)>, StorageError>;
+    /// Remove the items not matching the predicate at once, returns their count
+    fn retain(&self, keep: &dyn Fn(&K, &V) -> bool) -> Result<usize, StorageError>;
+}
+
+/// Changes made from the async code. The file storage writes the whole file,
+/// so they run on the blocking threads and don't stall the runtime
+#[async_trait]
+pub trait AsyncStorage<K, V> {
+    async fn set_async(&self, key: K, value: V) -> Result<(), StorageError>;
+    async fn retain_async<F>(&self, keep: F) -> Result<usize, StorageError>
+    where
+        F: Fn(&K, &V) -> bool + Send + 'static;
+}
+
+#[async_trait]
+impl<K, V> AsyncStorage<K, V> for Arc<dyn Storage<K, V>>
+where
+    K: Send + 'static,
+    V: Send + 'static,
+{
+    async fn set_async(&self, key: K, value: V) -> Result<(), StorageError> {
+        let storage = self.clone();
+        tokio::task::spawn_blocking(move || storage.set(key, value))
+            .await
+            .map_err(std::io::Error::from)?
+    }
+
+    async fn retain_async<F>(&self, keep: F) -> Result<usize, StorageError>
+    where
+        F: Fn(&K, &V) -> bool + Send + 'static,
+    {
+        let storage = self.clone();
+        tokio::task::spawn_blocking(move || storage.retain(&keep))
+            .await
+            .map_err(std::io::Error::from)?
+    }
+}
+
+/// Storage which lives until the bot restarts
+pub struct MemoryStorage<K, V> {
+    items: Mutex<HashMap<K, V>>,
+}
+
+impl<K, V> MemoryStorage<K, V> {
+    pub fn new() -> Self {
+        Self {
+            items: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, V> Default for MemoryStorage<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Storage<K, V> for MemoryStorage<K, V>
+where
+    K: Eq + Hash + Clone + Send,
+    V: Clone + Send,
+{
+    fn get(&self, key: &K) -> Result<Option<V>, StorageError> {
+        Ok(self.items.lock()?.get(key).cloned())
+    }
+
+    fn set(&self, key: K, value: V) -> Result<(), StorageError> {
+        self.items.lock()?.insert(key, value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &K) -> Result<Option<V>, StorageError> {
+        Ok(self.items.lock()?.remove(key))
+    }
+
+    fn all(&self) -> Result<Vec<(K, V)>, StorageError> {
+        Ok(self
+            .items
+            .lock()?
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+    fn retain(&self, keep: &dyn Fn(&K, &V) -> bool) -> Result<usize, StorageError> {
+        let mut items = self.items.lock()?;
+        let count = items.len();
+        items.retain(|key, value| keep(key, value));
+        Ok(count - items.len())
+    }
+}
+
+/// Storage which keeps a copy in memory and writes every change to a JSON file
+pub struct JsonFileStorage<K, V> {
+    path: PathBuf,
+    items: Mutex<HashMap<K, V>>,
+}
+
+impl<K, V> JsonFileStorage<K, V>
+where
+    K: Eq + Hash + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        let path = path.into();
+        let items = if path.exists() {
+            // Keys are not strings, so the map is stored as a list of pairs
+            let pairs: Vec<(K, V)> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            pairs.into_iter().collect()
+        } else {
+            HashMap::new()
+        };
+        info!("Open storage {:?} with {} items", path, items.len());
+
+        Ok(Self {
+            path,
+            items: Mutex::new(items),
+        })
+    }
+
+    fn save(&self, items: &HashMap<K, V>) -> Result<(), StorageError> {
+        let pairs: Vec<(&K, &V)> = items.iter().collect();
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(&pairs)?)?;
+        fs::rename(&tmp_path, &self.path).map_err(|err| {
+            error!("Can't save storage {:?}: {}", self.path, err);
+            err.into()
+        })
+    }
+}
+
+impl<K, V> Storage<K, V> for JsonFileStorage<K, V>
+where
+    K: Eq + Hash + Clone + Send + Serialize + DeserializeOwned,
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    fn get(&self, key: &K) -> Result<Option<V>, StorageError> {
+        Ok(self.items.lock()?.get(key).cloned())
+    }
+
+    /// The change is kept in memory only when the file is written
+    fn set(&self, key: K, value: V) -> Result<(), StorageError> {
+        let mut items = self.items.lock()?;
+        let mut changed = items.clone();
+        changed.insert(key, value);
+        self.save(&changed)?;
+        *items = changed;
+        Ok(())
+    }
+
+    fn remove(&self, key: &K) -> Result<Option<V>, StorageError> {
+        let mut items = self.items.lock()?;
+        let mut changed = items.clone();
+        let value = changed.remove(key);
+        if value.is_some() {
+            self.save(&changed)?;
+            *items = changed;
+        }
+        Ok(value)
+    }
+
+    fn all(&self) -> Result<Vec<(K, V)>, StorageError> {
+        Ok(self
+            .items
+            .lock()?
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+    fn retain(&self, keep: &dyn Fn(&K, &V) -> bool) -> Result<usize, StorageError> {
+        let mut items = self.items.lock()?;
+        let mut kept = items.clone();
+        kept.retain(|key, value| keep(key, value));
+        let removed = items.len() - kept.len();
+        if removed > 0 {
+            self.save(&kept)?;
+            *items = kept;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("weather_bot_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("storage.json")
+    }
+
+    #[test]
+    fn json_file_storage_is_reopened() {
+        let path = temp_path("reopen");
+        let _ = fs::remove_file(&path);
+
+        let storage = JsonFileStorage::<(i64, u64), String>::open(&path).unwrap();
+        storage.set((1, 2), "one".to_string()).unwrap();
+        storage.set((3, 4), "three".to_string()).unwrap();
+        storage.set((1, 2), "two".to_string()).unwrap();
+        assert_eq!(storage.remove(&(3, 4)).unwrap(), Some("three".to_string()));
+        assert_eq!(storage.remove(&(5, 6)).unwrap(), None);
+
+        let storage = JsonFileStorage::<(i64, u64), String>::open(&path).unwrap();
+        assert_eq!(storage.all().unwrap(), vec![((1, 2), "two".to_string())]);
+        assert_eq!(storage.get(&(3, 4)).unwrap(), None);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn changes_are_made_off_the_runtime() {
+        let path = temp_path("async");
+        let _ = fs::remove_file(&path);
+        let storage: Arc<dyn Storage<String, u32>> =
+            Arc::new(JsonFileStorage::open(&path).unwrap());
+
+        for (key, value) in [("one", 1), ("two", 2), ("three", 3)] {
+            storage.set_async(key.to_string(), value).await.unwrap();
+        }
+        assert_eq!(
+            storage
+                .retain_async(|_, value| value % 2 == 1)
+                .await
+                .unwrap(),
+            1
+        );
+
+        let mut all = JsonFileStorage::<String, u32>::open(&path)
+            .unwrap()
+            .all()
+            .unwrap();
+        all.sort();
+        assert_eq!(all, vec![("one".to_string(), 1), ("three".to_string(), 3)]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_are_not_kept() {
+        let path = temp_path("failed");
+        let storage = JsonFileStorage::<String, u32>::open(&path).unwrap();
+        storage.set("kept".to_string(), 1).unwrap();
+
+        // The temporary file can't be written into a missing directory
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(storage.set("lost".to_string(), 2).is_err());
+        assert!(storage.remove(&"kept".to_string()).is_err());
+        assert_eq!(storage.all().unwrap(), vec![("kept".to_string(), 1)]);
+    }
+}
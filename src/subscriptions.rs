@@ -0,0 +1,235 @@
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+
+use weather_bot::display::{display_forecast_telegram, display_place_header_telegram};
+use weather_bot::error::RequestError;
+use weather_bot::geo::GeoCoordinate;
+use weather_bot::settings::{self, Units};
+use weather_bot::storage::{AsyncStorage, Storage};
+use weather_bot::timezone::TimezoneFinder;
+use weather_bot::weather::{ForecastProvider, Weather};
+
+pub type SubscriptionStorage = Arc<dyn Storage<SubscriptionKey, Subscription>>;
+
+/// How often the subscriptions are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// A forecast which is late more than this is skipped till the next day
+const MAX_DELAY_MINUTES: i64 = 60;
+
+/// A chat has one subscription per city
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SubscriptionKey {
+    pub chat_id: i64,
+    pub city: String,
+}
+
+/// Daily forecast for the city sent to the chat at the local time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub coordinates: GeoCoordinate,
+    pub time: NaiveTime,
+    /// IANA timezone name, the timezone of the city is used if not set
+    pub timezone: Option<String>,
+    pub units: Units,
+    /// Local date of the last sent forecast
+    pub last_sent: Option<NaiveDate>,
+}
+
+impl SubscriptionKey {
+    pub fn new(chat_id: i64, city: &str) -> Self {
+        Self {
+            chat_id,
+            city: city.trim().to_string(),
+        }
+    }
+}
+
+impl Subscription {
+    fn get_timezone(&self) -> Option<Tz> {
+        self.timezone.as_deref().and_then(|name| name.parse().ok())
+    }
+
+    /// Whether the forecast for today is due at the given local time
+    fn is_due(&self, now: &DateTime<FixedOffset>) -> bool {
+        let today = now.date_naive();
+        let delay = now.time().signed_duration_since(self.time).num_minutes();
+
+        self.last_sent != Some(today) && (0..MAX_DELAY_MINUTES).contains(&delay)
+    }
+}
+
+/// Split `/subscribe` arguments into the city and the time
+pub fn parse_subscribe_args(args: &str) -> Result<(&str, NaiveTime), String> {
+    let usage = "Please enter the city and the time. Example: \n/subscribe Moscow 07:30";
+    let (city, time) = args.trim().rsplit_once(' ').ok_or(usage)?;
+    let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| usage.to_string())?;
+    let city = city.trim();
+    if city.is_empty() {
+        return Err(usage.to_string());
+    }
+
+    Ok((city, time))
+}
+
+pub fn display_subscriptions_telegram(subscriptions: &[(SubscriptionKey, Subscription)]) -> String {
+    if subscriptions.is_empty() {
+        return "There are no subscriptions in this chat".to_string();
+    }
+
+    subscriptions
+        .iter()
+        .map(|(key, subscription)| {
+            format!(
+                "{} at {} {}",
+                key.city,
+                subscription.time.format("%H:%M"),
+                subscription.timezone.as_deref().unwrap_or("local time"),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+async fn subscription_message(
+    provider: &dyn ForecastProvider,
+    timezones: &TimezoneFinder,
+    key: &SubscriptionKey,
+    subscription: &Subscription,
+) -> Result<String, RequestError> {
+    let weather = Weather::new(provider, subscription.coordinates).await?;
+    // Sent at the time of the subscriber but shown in the time of the city
+    let local_time = timezones.now_at(&subscription.coordinates);
+    let forecast = weather.get_forecast_at(&local_time)?;
+
+    Ok(format!(
+        "Daily forecast\n{}\n\n{}",
+        display_place_header_telegram(Some(&key.city), &subscription.coordinates, &local_time),
+        display_forecast_telegram(&forecast, &subscription.units)
+    ))
+}
+
+async fn send_due_forecasts(
+    bot: &Bot,
+    provider: &dyn ForecastProvider,
+    timezones: &TimezoneFinder,
+    subscriptions: &SubscriptionStorage,
+) {
+    let all = match subscriptions.all() {
+        Ok(all) => all,
+        Err(err) => {
+            error!("Can't load the subscriptions: {}", err);
+            return;
+        }
+    };
+
+    for (key, mut subscription) in all {
+        let timezone = subscription
+            .get_timezone()
+            .unwrap_or_else(|| timezones.find(&subscription.coordinates));
+        let now = settings::now_in_timezone(&timezone);
+        if !subscription.is_due(&now) {
+            continue;
+        }
+
+        info!(
+            "Send the daily forecast for {} to {}",
+            key.city, key.chat_id
+        );
+        match subscription_message(provider, timezones, &key, &subscription).await {
+            Ok(text) => {
+                let message = bot
+                    .send_message(ChatId(key.chat_id), text)
+                    .disable_web_page_preview(true);
+                if let Err(err) = message.await {
+                    error!("Can't send the daily forecast to {}: {}", key.chat_id, err);
+                }
+            }
+            Err(err) => error!("Can't get the daily forecast for {}: {}", key.city, err),
+        }
+
+        // Mark as sent anyway not to repeat failed requests every minute
+        subscription.last_sent = Some(now.date_naive());
+        if let Err(err) = subscriptions.set_async(key, subscription).await {
+            error!("Can't save the subscription: {}", err);
+        }
+    }
+}
+
+/// Send the daily forecasts of all subscriptions, never returns
+pub async fn run(
+    bot: Bot,
+    provider: Arc<dyn ForecastProvider>,
+    timezones: Arc<TimezoneFinder>,
+    subscriptions: SubscriptionStorage,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        send_due_forecasts(&bot, provider.as_ref(), &timezones, &subscriptions).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(last_sent: Option<NaiveDate>) -> Subscription {
+        Subscription {
+            coordinates: GeoCoordinate::new(55.75, 37.62),
+            time: NaiveTime::from_hms_opt(7, 30, 0).unwrap(),
+            timezone: None,
+            units: Units::default(),
+            last_sent,
+        }
+    }
+
+    fn time(text: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(text).unwrap()
+    }
+
+    #[test]
+    fn forecast_is_due_within_the_hour() {
+        let subscription = subscription(None);
+
+        assert!(subscription.is_due(&time("2023-10-18T07:30:00+03:00")));
+        assert!(subscription.is_due(&time("2023-10-18T08:29:00+03:00")));
+        assert!(!subscription.is_due(&time("2023-10-18T08:30:00+03:00")));
+        assert!(!subscription.is_due(&time("2023-10-18T07:29:00+03:00")));
+    }
+
+    #[test]
+    fn forecast_is_sent_once_a_day() {
+        let subscription = subscription(NaiveDate::from_ymd_opt(2023, 10, 18));
+
+        assert!(!subscription.is_due(&time("2023-10-18T07:30:00+03:00")));
+        assert!(subscription.is_due(&time("2023-10-19T07:30:00+03:00")));
+    }
+
+    #[test]
+    fn subscribe_args_are_parsed() {
+        let time = NaiveTime::from_hms_opt(7, 30, 0).unwrap();
+        assert_eq!(
+            parse_subscribe_args(" New York 07:30 "),
+            Ok(("New York", time))
+        );
+        assert_eq!(
+            parse_subscribe_args("55.75,37.62 07:30"),
+            Ok(("55.75,37.62", time))
+        );
+        assert_eq!(
+            parse_subscribe_args("55.75, 37.62 07:30"),
+            Ok(("55.75, 37.62", time))
+        );
+
+        for args in ["", "Moscow", "07:30", "Moscow 25:00", "Moscow 7.30"] {
+            assert!(parse_subscribe_args(args).is_err(), "{}", args);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use log::{info, warn};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::error::RequestError;
+use crate::geo::GeoCoordinate;
+use crate::geonames::GeoNames;
+
+/// A point farther than this from any city gets the nautical timezone
+const MAX_CITY_DISTANCE_KM: f64 = 300.0;
+
+/// Timezone lookup by the boundaries of the timezones. The points out of them
+/// are in UTC, unless the approximation is enabled
+#[derive(Debug, Clone, Default)]
+pub struct TimezoneFinder {
+    boundaries: Option<Arc<TimezoneBoundaries>>,
+    approximation: Option<Approximation>,
+}
+
+/// Guess of the timezone without the boundaries: the timezone of the nearest
+/// GeoNames city or the nautical timezone of the longitude. It is wrong near
+/// the borders and for the zones far from their meridian, e.g. in Spain
+#[derive(Debug, Clone, Default)]
+pub struct Approximation {
+    geonames: Option<Arc<GeoNames>>,
+}
+
+impl Approximation {
+    pub fn new(geonames: Option<Arc<GeoNames>>) -> Self {
+        Self { geonames }
+    }
+
+    fn find(&self, point: &GeoCoordinate) -> Tz {
+        self.geonames
+            .as_ref()
+            .and_then(|geonames| geonames.nearest(point))
+            .filter(|(_, distance)| *distance <= MAX_CITY_DISTANCE_KM)
+            .and_then(|(city, _)| city.timezone.parse().ok())
+            .unwrap_or_else(|| nautical_timezone(point))
+    }
+}
+
+impl TimezoneFinder {
+    pub fn new(
+        boundaries: Option<Arc<TimezoneBoundaries>>,
+        approximation: Option<Approximation>,
+    ) -> Self {
+        Self {
+            boundaries,
+            approximation,
+        }
+    }
+
+    pub fn find(&self, point: &GeoCoordinate) -> Tz {
+        self.boundaries
+            .as_ref()
+            .and_then(|boundaries| boundaries.find(point))
+            .or_else(|| {
+                self.approximation
+                    .as_ref()
+                    .map(|approximation| approximation.find(point))
+            })
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// Current local time at the point
+    pub fn now_at(&self, point: &GeoCoordinate) -> DateTime<FixedOffset> {
+        self.local_time(point, &Utc::now())
+    }
+
+    pub fn local_time(&self, point: &GeoCoordinate, time: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        time.with_timezone(&self.find(point)).fixed_offset()
+    }
+}
+
+/// Build the timezone lookup from `TIMEZONES_FILE`. Without the file the
+/// timezones are approximated, with it only when `TIMEZONES_APPROXIMATE` is set
+pub fn finder_from_env(geonames: Option<Arc<GeoNames>>) -> Result<TimezoneFinder, RequestError> {
+    let boundaries = match env::var("TIMEZONES_FILE") {
+        Ok(path) => Some(Arc::new(TimezoneBoundaries::load(&path)?)),
+        Err(_) => None,
+    };
+    let approximate =
+        env::var("TIMEZONES_APPROXIMATE").is_ok_and(|value| matches!(value.as_str(), "1" | "true"));
+    if boundaries.is_none() {
+        warn!("TIMEZONES_FILE is not set, the timezones are approximated");
+    }
+    let approximation = (approximate || boundaries.is_none()).then(|| Approximation::new(geonames));
+
+    Ok(TimezoneFinder::new(boundaries, approximation))
+}
+
+/// Whole hour offset of every 15° of longitude
+fn nautical_timezone(point: &GeoCoordinate) -> Tz {
+    let hours = (point.longitude / 15.0).round() as i32;
+    // The signs of the Etc/GMT zones are inverted
+    let name = match hours {
+        0 => "Etc/GMT".to_string(),
+        hours if hours > 0 => format!("Etc/GMT-{}", hours),
+        hours => format!("Etc/GMT+{}", -hours),
+    };
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// Boundaries of the timezones from the GeoJSON of timezone-boundary-builder,
+/// see https://github.com/evansiroky/timezone-boundary-builder/releases
+#[derive(Debug, Default)]
+pub struct TimezoneBoundaries {
+    zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    timezone: Tz,
+    /// Lower left and upper right corners to skip the zone quickly
+    lower: GeoCoordinate,
+    upper: GeoCoordinate,
+    polygons: Vec<Polygon>,
+}
+
+/// Outer ring first, then the holes. The positions are `[longitude, latitude]`
+type Polygon = Vec<Vec<[f64; 2]>>;
+
+#[derive(Debug, Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Feature {
+    properties: FeatureProperties,
+    geometry: Geometry,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeatureProperties {
+    tzid: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Polygon { coordinates: Polygon },
+    MultiPolygon { coordinates: Vec<Polygon> },
+}
+
+impl TimezoneBoundaries {
+    pub fn load(path: &str) -> Result<Self, RequestError> {
+        let file = File::open(path)
+            .map_err(|err| RequestError::Config(format!("can't read {}: {}", path, err)))?;
+        let collection: FeatureCollection = serde_json::from_reader(BufReader::new(file))?;
+        let boundaries = Self::from_features(collection.features);
+        info!(
+            "Loaded the boundaries of {} timezones from {}",
+            boundaries.zones.len(),
+            path
+        );
+
+        Ok(boundaries)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RequestError> {
+        let collection: FeatureCollection = serde_json::from_str(text)?;
+        Ok(Self::from_features(collection.features))
+    }
+
+    fn from_features(features: Vec<Feature>) -> Self {
+        let zones = features
+            .into_iter()
+            .filter_map(|feature| {
+                let Ok(timezone) = feature.properties.tzid.parse::<Tz>() else {
+                    // The timezone database of chrono-tz may be older
+                    warn!("Unknown timezone {}", feature.properties.tzid);
+                    return None;
+                };
+                let polygons = match feature.geometry {
+                    Geometry::Polygon { coordinates } => vec![coordinates],
+                    Geometry::MultiPolygon { coordinates } => coordinates,
+                };
+                Zone::new(timezone, polygons)
+            })
+            .collect();
+
+        Self { zones }
+    }
+
+    pub fn find(&self, point: &GeoCoordinate) -> Option<Tz> {
+        self.zones
+            .iter()
+            .find(|zone| zone.contains(point))
+            .map(|zone| zone.timezone)
+    }
+}
+
+impl Zone {
+    /// `None` for a zone without points
+    fn new(timezone: Tz, polygons: Vec<Polygon>) -> Option<Self> {
+        let mut positions = polygons
+            .iter()
+            .filter_map(|polygon| polygon.first())
+            .flatten();
+        let [lon, lat] = *positions.next()?;
+        let (mut lower, mut upper) = (GeoCoordinate::new(lat, lon), GeoCoordinate::new(lat, lon));
+        for [lon, lat] in positions {
+            lower = GeoCoordinate::new(lower.latitude.min(*lat), lower.longitude.min(*lon));
+            upper = GeoCoordinate::new(upper.latitude.max(*lat), upper.longitude.max(*lon));
+        }
+
+        Some(Self {
+            timezone,
+            lower,
+            upper,
+            polygons,
+        })
+    }
+
+    fn contains(&self, point: &GeoCoordinate) -> bool {
+        let in_box = (self.lower.latitude..=self.upper.latitude).contains(&point.latitude)
+            && (self.lower.longitude..=self.upper.longitude).contains(&point.longitude);
+
+        in_box
+            && self.polygons.iter().any(|polygon| {
+                let mut rings = polygon.iter();
+                rings
+                    .next()
+                    .is_some_and(|outer| ring_contains(outer, point))
+                    && !rings.any(|hole| ring_contains(hole, point))
+            })
+    }
+}
+
+/// Even-odd rule: a ray from the point crosses the ring an odd number of times
+fn ring_contains(ring: &[[f64; 2]], point: &GeoCoordinate) -> bool {
+    let (x, y) = (point.longitude, point.latitude);
+    let mut inside = false;
+    for (i, [x1, y1]) in ring.iter().enumerate() {
+        let [x2, y2] = ring[(i + ring.len() - 1) % ring.len()];
+        if (*y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn boundaries() -> Arc<TimezoneBoundaries> {
+        Arc::new(
+            TimezoneBoundaries::parse(include_str!("../tests/fixtures/timezones/boundaries.json"))
+                .unwrap(),
+        )
+    }
+
+    fn approximation() -> Approximation {
+        let geonames =
+            GeoNames::parse(include_str!("../tests/fixtures/geonames/cities.txt")).unwrap();
+        Approximation::new(Some(Arc::new(geonames)))
+    }
+
+    #[test]
+    fn timezone_is_found_by_the_boundaries() {
+        let finder = TimezoneFinder::new(Some(boundaries()), None);
+
+        let madrid = GeoCoordinate::new(40.42, -3.70);
+        assert_eq!(finder.find(&madrid), chrono_tz::Europe::Madrid);
+        // The summer time is applied
+        let time = Utc.with_ymd_and_hms(2023, 7, 15, 12, 0, 0).unwrap();
+        assert_eq!(
+            finder.local_time(&madrid, &time).to_rfc3339(),
+            "2023-07-15T14:00:00+02:00"
+        );
+        let time = Utc.with_ymd_and_hms(2023, 12, 15, 12, 0, 0).unwrap();
+        assert_eq!(
+            finder.local_time(&madrid, &time).to_rfc3339(),
+            "2023-12-15T13:00:00+01:00"
+        );
+
+        // Andorra is a hole in Spain
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(42.5, 1.5)),
+            chrono_tz::Europe::Andorra
+        );
+        // Of the multipolygon
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(39.6, 2.9)),
+            chrono_tz::Europe::Madrid
+        );
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(38.72, -9.14)),
+            chrono_tz::Europe::Lisbon
+        );
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(30.0, -30.0)),
+            chrono_tz::Etc::GMTPlus2
+        );
+    }
+
+    #[test]
+    fn points_out_of_the_boundaries_are_in_utc() {
+        let finder = TimezoneFinder::new(Some(boundaries()), None);
+        assert_eq!(finder.find(&GeoCoordinate::new(55.8, 37.5)), Tz::UTC);
+        assert_eq!(
+            TimezoneFinder::default().find(&GeoCoordinate::new(55.8, 37.5)),
+            Tz::UTC
+        );
+    }
+
+    #[test]
+    fn approximation_is_used_out_of_the_boundaries() {
+        let finder = TimezoneFinder::new(Some(boundaries()), Some(approximation()));
+
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(40.42, -3.70)),
+            chrono_tz::Europe::Madrid
+        );
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(55.8, 37.5)),
+            chrono_tz::Europe::Moscow
+        );
+        let time = Utc.with_ymd_and_hms(2023, 10, 18, 22, 30, 0).unwrap();
+        let local = finder.local_time(&GeoCoordinate::new(55.8, 37.5), &time);
+        assert_eq!(local.to_rfc3339(), "2023-10-19T01:30:00+03:00");
+    }
+
+    #[test]
+    fn approximation_falls_back_to_the_nautical_timezone() {
+        assert_eq!(
+            approximation().find(&GeoCoordinate::new(0.0, -31.0)),
+            chrono_tz::Etc::GMTPlus2
+        );
+        let finder = TimezoneFinder::new(None, Some(Approximation::default()));
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(55.8, 37.5)),
+            chrono_tz::Etc::GMTMinus3
+        );
+        assert_eq!(
+            finder.find(&GeoCoordinate::new(51.5, -0.1)),
+            chrono_tz::Etc::GMT
+        );
+    }
+}
@@ -1,20 +1,28 @@
 use log::{debug, error, info, warn};
 use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, PoisonError};
 
+use async_trait::async_trait;
 use chrono::prelude::*;
-use serde_json::Value;
+use chrono::Duration;
+use chrono_tz::Tz;
+use reqwest::header::{HeaderMap, HeaderName, EXPIRES, IF_MODIFIED_SINCE, LAST_MODIFIED};
+use reqwest::StatusCode;
 
+use crate::error::RequestError;
 use crate::geo::GeoCoordinate;
-use crate::weather_codes::get_weather_code;
-use crate::weather_codes::WeatherCode;
+use crate::http::HttpClient;
+use crate::metno;
+use crate::openmeteo::OpenMeteo;
+use crate::openweathermap::OpenWeatherMap;
+use crate::weather_codes::{WeatherCode, WeatherSymbol};
 
-static APP_USER_AGENT: &str = concat!(
-    env!("CARGO_PKG_NAME"),
-    "/",
-    env!("CARGO_PKG_VERSION"),
-    " ",
-    env!("CARGO_PKG_REPOSITORY")
-);
+/// Expired responses are still kept this long for the revalidation
+const CACHE_KEEP_EXPIRED_HOURS: i64 = 24;
+const SECONDS_PER_HOUR: i64 = 3600;
+/// Half of the longest timeseries step, a farther point describes another time
+const MAX_SLOT_DISTANCE_SECONDS: i64 = 3 * SECONDS_PER_HOUR;
 
 #[derive(Debug)]
 pub struct Weather {
@@ -24,7 +32,8 @@ pub struct Weather {
 
 pub type Forecasts = HashMap<DateTime<FixedOffset>, Forecast>;
 
-#[derive(Debug, Clone)]
+/// Provider neutral forecast for a point of time in ℃, hPa, m/s, % and mm
+#[derive(Debug, Clone, PartialEq)]
 pub struct Forecast {
     pub air_pressure_at_sea_level: f64,
     pub air_temperature: f64,
@@ -32,46 +41,322 @@ pub struct Forecast {
     pub relative_humidity: f64,
     pub wind_from_direction: f64,
     pub wind_speed: f64,
-    forecast_by_time: NextHoursForecast,
+    pub wind_speed_of_gust: Option<f64>,
+    pub dew_point_temperature: Option<f64>,
+    pub fog_area_fraction: Option<f64>,
+    pub ultraviolet_index_clear_sky: Option<f64>,
+    pub cloud_area_fraction_high: Option<f64>,
+    pub cloud_area_fraction_medium: Option<f64>,
+    pub cloud_area_fraction_low: Option<f64>,
+    pub forecast_by_time: NextHoursForecast,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-enum Hours {
+pub enum Hours {
     Hour1,
+    Hour3,
     Hour6,
     Hour12,
 }
 
-type NextHoursForecast = HashMap<Hours, WeatherCode>;
-
-// #[derive(Debug)]
-// pub enum RequestError {
-//     General(String),
-//     Reqwest(reqwest::Error),
-// }
-
-// impl std::fmt::Display for RequestError {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         match self {
-//             RequestError::General(s) => write!(f, "{}", s),
-//             RequestError::Reqwest(_e) => write!(f, ""),
-//         }
-//     }
-// }
-
-// impl error::Error for RequestError {
-//     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-//         match self {
-//             RequestError::General(_) => None,
-//             RequestError::Reqwest(e) => Some(e),
-//         }
-//     }
-// }
+impl Hours {
+    pub fn duration(&self) -> i64 {
+        match self {
+            Hours::Hour1 => 1,
+            Hours::Hour3 => 3,
+            Hours::Hour6 => 6,
+            Hours::Hour12 => 12,
+        }
+    }
+}
+
+/// Summary of the period following the forecast time
+#[derive(Debug, Clone, PartialEq)]
+pub struct NextHours {
+    pub symbol: WeatherSymbol,
+    pub precipitation_amount: Option<f64>,
+    pub precipitation_amount_min: Option<f64>,
+    pub precipitation_amount_max: Option<f64>,
+    pub probability_of_precipitation: Option<f64>,
+}
+
+pub type NextHoursForecast = HashMap<Hours, NextHours>;
+
+/// Summary of one local day of the forecast
+#[derive(Debug, Clone)]
+pub struct DayForecast {
+    pub date: NaiveDate,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    pub weather_code: Option<WeatherCode>,
+    pub precipitation_amount: f64,
+    pub max_wind_speed: f64,
+}
+
+impl Forecast {
+    pub fn get_next_hours(&self, hours: Hours) -> Option<&NextHours> {
+        self.forecast_by_time.get(&hours)
+    }
+
+    /// The shortest period that follows the forecast time
+    pub fn nearest_period(&self) -> Option<(Hours, &NextHours)> {
+        [Hours::Hour1, Hours::Hour3, Hours::Hour6, Hours::Hour12]
+            .into_iter()
+            .find_map(|hours| self.forecast_by_time.get(&hours).map(|next| (hours, next)))
+    }
+}
+
+/// Source of the forecast timeseries for a point
+#[async_trait]
+pub trait ForecastProvider: Send + Sync {
+    /// Short name for the logs, the config and the user settings
+    fn name(&self) -> &'static str;
+
+    async fn get_forecasts(&self, geo_point: &GeoCoordinate) -> Result<Forecasts, RequestError>;
+}
+
+/// Forecast provider backed by the met.no Locationforecast API
+#[derive(Debug)]
+pub struct MetNo {
+    client: HttpClient,
+    cache: Mutex<HashMap<CacheKey, CachedResponse>>,
+}
+
+/// Coordinates rounded to 4 decimals as the met.no terms require
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    latitude: String,
+    longitude: String,
+}
+
+/// Response body kept until it expires and revalidated afterwards
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    body: String,
+    expires: Option<DateTime<Utc>>,
+    last_modified: Option<String>,
+}
+
+impl CacheKey {
+    fn new(geo_point: &GeoCoordinate) -> Self {
+        Self {
+            latitude: format!("{:.4}", geo_point.latitude),
+            longitude: format!("{:.4}", geo_point.longitude),
+        }
+    }
+}
+
+impl CachedResponse {
+    fn is_fresh(&self, now: &DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| *now < expires)
+    }
+}
+
+/// Parse an HTTP date header like `Expires`
+fn header_date(headers: &HeaderMap, name: HeaderName) -> Option<DateTime<Utc>> {
+    let value = headers.get(name)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+impl MetNo {
+    pub fn new(client: HttpClient) -> Self {
+        Self {
+            client,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<CachedResponse> {
+        // The cache is consistent even if a thread panicked while holding it
+        let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        cache.get(key).cloned()
+    }
+
+    fn store(&self, key: CacheKey, response: CachedResponse) {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Utc::now();
+        cache.retain(|_, cached| {
+            cached
+                .expires
+                .is_some_and(|expires| now - expires < Duration::hours(CACHE_KEEP_EXPIRED_HOURS))
+        });
+        cache.insert(key, response);
+    }
+
+    async fn request_api(&self, geo_point: &GeoCoordinate) -> Result<String, RequestError> {
+        let key = CacheKey::new(geo_point);
+        let cached = self.cached(&key);
+        if let Some(cached) = cached
+            .as_ref()
+            .filter(|cached| cached.is_fresh(&Utc::now()))
+        {
+            debug!("Forecast for {:?} is served from the cache", key);
+            return Ok(cached.body.clone());
+        }
+
+        let api_url = format!(
+            "https://api.met.no/weatherapi/locationforecast/2.0/complete?lat={}&lon={}",
+            key.latitude, key.longitude
+        );
+
+        info!("Request API: {}...", api_url);
+
+        let mut request = self.client.get(api_url);
+        if let Some(last_modified) = cached
+            .as_ref()
+            .and_then(|cached| cached.last_modified.as_ref())
+        {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let res = self.client.send(request).await?;
+        let status = res.status();
+        let expires = header_date(res.headers(), EXPIRES);
+
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(mut cached) = cached {
+                debug!("Forecast for {:?} is not modified", key);
+                cached.expires = expires;
+                let body = cached.body.clone();
+                self.store(key, cached);
+                return Ok(body);
+            }
+        }
+
+        if !status.is_success() {
+            error!("Error: {}", status);
+            return Err(RequestError::from_status(status));
+        }
+
+        let last_modified = res
+            .headers()
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let text_response = res.text().await?;
+        self.store(
+            key,
+            CachedResponse {
+                body: text_response.clone(),
+                expires,
+                last_modified,
+            },
+        );
+
+        Ok(text_response)
+    }
+}
+
+#[async_trait]
+impl ForecastProvider for MetNo {
+    fn name(&self) -> &'static str {
+        "metno"
+    }
+
+    async fn get_forecasts(&self, geo_point: &GeoCoordinate) -> Result<Forecasts, RequestError> {
+        let response = self.request_api(geo_point).await?;
+        metno::parse_response(&response)
+    }
+}
+
+/// Tries the providers in order until one of them succeeds
+#[derive(Clone)]
+pub struct FailoverProvider {
+    providers: Vec<Arc<dyn ForecastProvider>>,
+}
+
+impl FailoverProvider {
+    pub fn new(providers: Vec<Arc<dyn ForecastProvider>>) -> Self {
+        Self { providers }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect()
+    }
+
+    /// The same providers with the named one tried first
+    pub fn preferring(&self, name: Option<&str>) -> FailoverProvider {
+        let mut providers = self.providers.clone();
+        if let Some(index) = providers
+            .iter()
+            .position(|provider| Some(provider.name()) == name)
+        {
+            let provider = providers.remove(index);
+            providers.insert(0, provider);
+        }
+        Self { providers }
+    }
+}
+
+#[async_trait]
+impl ForecastProvider for FailoverProvider {
+    fn name(&self) -> &'static str {
+        "failover"
+    }
+
+    async fn get_forecasts(&self, geo_point: &GeoCoordinate) -> Result<Forecasts, RequestError> {
+        let mut last_error = RequestError::Config("no forecast providers".to_string());
+        for provider in &self.providers {
+            match provider.get_forecasts(geo_point).await {
+                Ok(forecasts) => return Ok(forecasts),
+                Err(err) => {
+                    warn!(
+                        "Provider {} failed for {:?}: {}",
+                        provider.name(),
+                        geo_point,
+                        err
+                    );
+                    last_error = err;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// Build the providers from `FORECAST_PROVIDERS`, e.g. `metno,openmeteo,openweathermap`
+pub fn provider_from_env(client: HttpClient) -> Result<FailoverProvider, RequestError> {
+    let names = env::var("FORECAST_PROVIDERS").unwrap_or_else(|_| "metno".to_string());
+
+    let mut providers: Vec<Arc<dyn ForecastProvider>> = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let provider: Arc<dyn ForecastProvider> = match name.to_lowercase().as_str() {
+            "metno" => Arc::new(MetNo::new(client.clone())),
+            "openmeteo" => Arc::new(OpenMeteo::new(client.clone())),
+            "openweathermap" => Arc::new(OpenWeatherMap::from_env(client.clone())?),
+            _ => {
+                return Err(RequestError::Config(format!(
+                    "unknown forecast provider \"{}\" in FORECAST_PROVIDERS",
+                    name
+                )))
+            }
+        };
+        info!("Use the forecast provider: {}", provider.name());
+        providers.push(provider);
+    }
+
+    if providers.is_empty() {
+        return Err(RequestError::Config(
+            "FORECAST_PROVIDERS is empty".to_string(),
+        ));
+    }
+    Ok(FailoverProvider::new(providers))
+}
 
 impl Weather {
-    pub async fn new(geo_point: GeoCoordinate) -> anyhow::Result<Self> {
-        let response = Weather::request_api(geo_point.latitude, geo_point.longitude).await?;
-        let forecasts = parse_api_response(response);
+    pub async fn new(
+        provider: &dyn ForecastProvider,
+        geo_point: GeoCoordinate,
+    ) -> Result<Self, RequestError> {
+        let forecasts = provider.get_forecasts(&geo_point).await?;
 
         Ok(Self {
             forecasts,
@@ -103,34 +388,207 @@ impl Weather {
     // }
     // }
 
-    async fn request_api(lat: f64, lon: f64) -> anyhow::Result<String> {
-        let api_url = format!(
-            "https://api.met.no/weatherapi/locationforecast/2.0/compact?lat={}&lon={}",
-            lat, lon
-        );
+    /// Conditions at any instant inside the horizon. The values are linearly
+    /// interpolated between the surrounding timeseries points. The timeseries
+    /// may start at the next step after now, e.g. the 3 hour steps of
+    /// OpenWeatherMap, so the first point is taken for up to one step before it
+    pub fn get_forecast_at(&self, time: &DateTime<FixedOffset>) -> Result<Forecast, RequestError> {
+        let before = self
+            .forecasts
+            .iter()
+            .filter(|(key, _)| *key <= time)
+            .max_by_key(|(key, _)| **key);
+        let after = self
+            .forecasts
+            .iter()
+            .filter(|(key, _)| *key >= time)
+            .min_by_key(|(key, _)| **key);
 
-        let client = reqwest::Client::builder()
-            .user_agent(APP_USER_AGENT)
-            .build()
-            .unwrap();
+        match (before, after) {
+            (Some((from, before)), Some((till, after))) => {
+                let step = (*till - *from).num_seconds();
+                if step == 0 {
+                    return Ok(before.clone());
+                }
+                let ratio = (*time - *from).num_seconds() as f64 / step as f64;
+                Ok(before.interpolate(after, ratio))
+            }
+            (None, Some((first, forecast))) if (*first - *time) <= self.first_step() => {
+                Ok(forecast.clone())
+            }
+            _ => {
+                let from = self.forecasts.keys().min();
+                let till = self.forecasts.keys().max();
+                match (from, till) {
+                    (Some(from), Some(till)) => Err(RequestError::OutOfHorizon {
+                        time: *time,
+                        from: *from,
+                        till: *till,
+                    }),
+                    _ => Err(RequestError::NoForecast(*time)),
+                }
+            }
+        }
+    }
 
-        info!("Agent: {}", APP_USER_AGENT);
-        info!("Request API: {}...", api_url);
+    /// Time between the first two points of the timeseries
+    fn first_step(&self) -> Duration {
+        let mut times: Vec<&DateTime<FixedOffset>> = self.forecasts.keys().collect();
+        times.sort();
+        match times[..] {
+            [first, second, ..] => *second - *first,
+            _ => Duration::seconds(MAX_SLOT_DISTANCE_SECONDS),
+        }
+    }
 
-        let text_response = client.get(api_url).send().await?.text().await?;
+    /// Group the whole timeseries by local day of the given timezone. The
+    /// timezone and not the current offset is taken, so the days after
+    /// a daylight saving time change are not shifted
+    pub fn get_daily_forecasts(&self, timezone: &Tz) -> Vec<DayForecast> {
+        let mut times: Vec<&DateTime<FixedOffset>> = self.forecasts.keys().collect();
+        times.sort();
 
-        Ok(text_response)
+        let mut days: Vec<DayForecast> = Vec::new();
+        let mut weather_codes: Vec<(WeatherCode, i64)> = Vec::new();
+        // Hourly steps turn into 6 hour steps at the end of the horizon,
+        // so the precipitation of the step is taken from the shortest period
+        let mut covered_until: Option<DateTime<FixedOffset>> = None;
+
+        for time in times {
+            let forecast = &self.forecasts[time];
+            let date = time.with_timezone(timezone).date_naive();
+
+            if days.last().map(|day| day.date) != Some(date) {
+                if let Some(day) = days.last_mut() {
+                    day.weather_code = dominant_weather_code(&weather_codes);
+                }
+                weather_codes.clear();
+                days.push(DayForecast {
+                    date,
+                    min_temperature: forecast.air_temperature,
+                    max_temperature: forecast.air_temperature,
+                    weather_code: None,
+                    precipitation_amount: 0.0,
+                    max_wind_speed: forecast.wind_speed,
+                });
+            }
+
+            let day = days.last_mut().unwrap();
+            day.min_temperature = day.min_temperature.min(forecast.air_temperature);
+            day.max_temperature = day.max_temperature.max(forecast.air_temperature);
+            day.max_wind_speed = day.max_wind_speed.max(forecast.wind_speed);
+
+            if covered_until.is_none_or(|until| *time >= until) {
+                if let Some((hours, next)) = forecast.nearest_period() {
+                    day.precipitation_amount += next.precipitation_amount.unwrap_or(0.0);
+                    match weather_codes
+                        .iter_mut()
+                        .find(|(code, _)| *code == next.symbol.code)
+                    {
+                        Some((_, weight)) => *weight += hours.duration(),
+                        None => weather_codes.push((next.symbol.code.clone(), hours.duration())),
+                    }
+                    covered_until = Some(*time + Duration::hours(hours.duration()));
+                }
+            }
+        }
+        if let Some(day) = days.last_mut() {
+            day.weather_code = dominant_weather_code(&weather_codes);
+        }
+
+        days
+    }
+}
+
+fn lerp(a: f64, b: f64, ratio: f64) -> f64 {
+    a + (b - a) * ratio
+}
+
+/// Interpolate the optional value, the nearer value is taken if one is missing
+fn lerp_option(a: Option<f64>, b: Option<f64>, ratio: f64) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(lerp(a, b, ratio)),
+        _ if ratio < 0.5 => a,
+        _ => b,
     }
+}
+
+/// Interpolate the direction in degrees along the shorter arc,
+/// so 350° and 10° give 0° rather than 180°
+fn lerp_direction(a: f64, b: f64, ratio: f64) -> f64 {
+    let delta = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+    (a + delta * ratio).rem_euclid(360.0)
+}
 
-    pub fn get_forecast_for_hour(&self, time: &DateTime<FixedOffset>) -> Option<&Forecast> {
-        info!("Get forecast for {:?}", time);
-        self.forecasts.get(time)
+impl Forecast {
+    /// The forecast between this one and the next one, `ratio` is 0 here and 1 there.
+    /// The next hours summaries are kept from this forecast as they cover the time
+    fn interpolate(&self, next: &Forecast, ratio: f64) -> Forecast {
+        Forecast {
+            air_pressure_at_sea_level: lerp(
+                self.air_pressure_at_sea_level,
+                next.air_pressure_at_sea_level,
+                ratio,
+            ),
+            air_temperature: lerp(self.air_temperature, next.air_temperature, ratio),
+            cloud_area_fraction: lerp(self.cloud_area_fraction, next.cloud_area_fraction, ratio),
+            relative_humidity: lerp(self.relative_humidity, next.relative_humidity, ratio),
+            wind_from_direction: lerp_direction(
+                self.wind_from_direction,
+                next.wind_from_direction,
+                ratio,
+            ),
+            wind_speed: lerp(self.wind_speed, next.wind_speed, ratio),
+            wind_speed_of_gust: lerp_option(
+                self.wind_speed_of_gust,
+                next.wind_speed_of_gust,
+                ratio,
+            ),
+            dew_point_temperature: lerp_option(
+                self.dew_point_temperature,
+                next.dew_point_temperature,
+                ratio,
+            ),
+            fog_area_fraction: lerp_option(self.fog_area_fraction, next.fog_area_fraction, ratio),
+            ultraviolet_index_clear_sky: lerp_option(
+                self.ultraviolet_index_clear_sky,
+                next.ultraviolet_index_clear_sky,
+                ratio,
+            ),
+            cloud_area_fraction_high: lerp_option(
+                self.cloud_area_fraction_high,
+                next.cloud_area_fraction_high,
+                ratio,
+            ),
+            cloud_area_fraction_medium: lerp_option(
+                self.cloud_area_fraction_medium,
+                next.cloud_area_fraction_medium,
+                ratio,
+            ),
+            cloud_area_fraction_low: lerp_option(
+                self.cloud_area_fraction_low,
+                next.cloud_area_fraction_low,
+                ratio,
+            ),
+            forecast_by_time: self.forecast_by_time.clone(),
+        }
     }
 }
 
+/// The weather code which covers the most hours, the earliest one wins a tie
+fn dominant_weather_code(weather_codes: &[(WeatherCode, i64)]) -> Option<WeatherCode> {
+    weather_codes
+        .iter()
+        .fold(None, |best: Option<&(WeatherCode, i64)>, item| match best {
+            Some(best) if best.1 >= item.1 => Some(best),
+            _ => Some(item),
+        })
+        .map(|(code, _)| code.clone())
+}
+
 impl std::fmt::Display for Weather {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.forecasts)
+        write!(f, "{:?} {:?}", self.coordinates, self.forecasts)
     }
 }
 
@@ -139,7 +597,7 @@ impl std::fmt::Display for Forecast {
         write!(
             f,
             "Forecast(Air Pressure: {}\n Temperature: {}\n Humidity: {}\n 
-                Cloud: {}\n Relative Humidity: {}\n Wind Direction: {}\n Wind Speed: {})",
+                Cloud: {}\n Relative Humidity: {}\n Wind Direction: {}\n Wind Speed: {}\n Next Hours: {:?})",
             self.air_pressure_at_sea_level,
             self.air_temperature,
             self.relative_humidity,
@@ -147,58 +605,254 @@ impl std::fmt::Display for Forecast {
             self.relative_humidity,
             self.wind_from_direction,
             self.wind_speed,
+            self.forecast_by_time,
         )
     }
 }
 
-fn parse_api_response(text_response: String) -> Forecasts {
-    let mut forecasts: Forecasts = Default::default();
-    let v: Value = serde_json::from_str(&text_response).unwrap();
-    let timeseries = &v["properties"]["timeseries"];
+impl From<metno::ForecastPeriod> for NextHours {
+    fn from(period: metno::ForecastPeriod) -> Self {
+        let details = period.details;
+        NextHours {
+            symbol: WeatherSymbol::from(period.summary.symbol_code.as_str()),
+            precipitation_amount: details.precipitation_amount,
+            precipitation_amount_min: details.precipitation_amount_min,
+            precipitation_amount_max: details.precipitation_amount_max,
+            probability_of_precipitation: details.probability_of_precipitation,
+        }
+    }
+}
 
-    for hour in 0..24 {
-        let values = &timeseries[hour]["data"]["instant"]["details"];
-        let values_by_time = &timeseries[hour]["data"];
-        // info!("{:#?}", values_by_time);
-        let air_pressure_at_sea_level = values["air_pressure_at_sea_level"].as_f64().unwrap();
+impl From<metno::ForecastData> for Forecast {
+    fn from(data: metno::ForecastData) -> Self {
+        let details = data.instant.details;
+        let forecast_by_time: NextHoursForecast = [
+            (Hours::Hour1, data.next_1_hours),
+            (Hours::Hour6, data.next_6_hours),
+            (Hours::Hour12, data.next_12_hours),
+        ]
+        .into_iter()
+        .filter_map(|(hours, period)| period.map(|period| (hours, period.into())))
+        .collect();
 
-        debug!("{:#?}", values);
-        let air_temperature = values["air_temperature"].as_f64().unwrap();
+        Forecast {
+            air_pressure_at_sea_level: details.air_pressure_at_sea_level,
+            air_temperature: details.air_temperature,
+            cloud_area_fraction: details.cloud_area_fraction,
+            relative_humidity: details.relative_humidity,
+            wind_from_direction: details.wind_from_direction,
+            wind_speed: details.wind_speed,
+            wind_speed_of_gust: details.wind_speed_of_gust,
+            dew_point_temperature: details.dew_point_temperature,
+            fog_area_fraction: details.fog_area_fraction,
+            ultraviolet_index_clear_sky: details.ultraviolet_index_clear_sky,
+            cloud_area_fraction_high: details.cloud_area_fraction_high,
+            cloud_area_fraction_medium: details.cloud_area_fraction_medium,
+            cloud_area_fraction_low: details.cloud_area_fraction_low,
+            forecast_by_time,
+        }
+    }
+}
 
-        let cloud_area_fraction = values["cloud_area_fraction"].as_f64().unwrap();
-        let relative_humidity = values["relative_humidity"].as_f64().unwrap();
-        let wind_from_direction = values["wind_from_direction"].as_f64().unwrap();
-        let wind_speed = values["wind_speed"].as_f64().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
 
-        let forecast_by_time_12 = values_by_time["next_12_hours"]["summary"]["symbol_code"]
-            .as_str()
-            .unwrap();
-        let forecast_by_time_6 = values_by_time["next_6_hours"]["summary"]["symbol_code"]
-            .as_str()
-            .unwrap();
-        let forecast_by_time_1 = values_by_time["next_1_hours"]["summary"]["symbol_code"]
-            .as_str()
-            .unwrap();
-        let forecast_by_time = HashMap::from([
-            (Hours::Hour1, get_weather_code(forecast_by_time_1)),
-            (Hours::Hour6, get_weather_code(forecast_by_time_6)),
-            (Hours::Hour12, get_weather_code(forecast_by_time_12)),
+    #[test]
+    fn response_is_parsed() {
+        let forecasts =
+            metno::parse_response(include_str!("../tests/fixtures/response.json")).unwrap();
+
+        let time = DateTime::parse_from_rfc3339("2023-07-15T08:00:00Z").unwrap();
+        let forecast = &forecasts[&time];
+        assert!(forecast.get_next_hours(Hours::Hour1).is_some());
+        assert!(forecast.get_next_hours(Hours::Hour6).is_some());
+        assert_eq!(forecast.air_temperature, 12.1);
+
+        // The periods end before the last steps of the horizon
+        let time = DateTime::parse_from_rfc3339("2023-07-25T06:00:00Z").unwrap();
+        let forecast = &forecasts[&time];
+        assert!(forecast.forecast_by_time.is_empty());
+        assert_eq!(forecasts.len(), 84);
+    }
+
+    #[test]
+    fn unexpected_units_are_rejected() {
+        let response = include_str!("../tests/fixtures/response.json").replace(
+            r#""air_temperature": "celsius""#,
+            r#""air_temperature": "fahrenheit""#,
+        );
+        assert!(matches!(
+            metno::parse_response(&response),
+            Err(RequestError::Parse(message)) if message.contains("air_temperature")
+        ));
+    }
+
+    #[test]
+    fn failover_prefers_the_named_provider() {
+        let client = HttpClient::new(reqwest::Client::new());
+        let providers = FailoverProvider::new(vec![
+            Arc::new(MetNo::new(client.clone())),
+            Arc::new(OpenMeteo::new(client)),
         ]);
-        let forecast = Forecast {
-            air_pressure_at_sea_level,
-            air_temperature,
-            cloud_area_fraction,
-            relative_humidity,
-            wind_from_direction,
-            wind_speed,
-            forecast_by_time,
+
+        assert_eq!(providers.names(), vec!["metno", "openmeteo"]);
+        assert_eq!(
+            providers.preferring(Some("openmeteo")).names(),
+            vec!["openmeteo", "metno"]
+        );
+        assert_eq!(
+            providers.preferring(Some("unknown")).names(),
+            vec!["metno", "openmeteo"]
+        );
+    }
+
+    #[test]
+    fn forecasts_are_grouped_by_day() {
+        let weather = Weather {
+            forecasts: metno::parse_response(include_str!("../tests/fixtures/response.json"))
+                .unwrap(),
+            coordinates: GeoCoordinate::new(59.91, 10.75),
         };
-        // "2023-07-15T08:00:00Z"
-        let datetime_str = &timeseries[hour]["time"].as_str().unwrap();
-        debug!("Time {:?}", datetime_str);
-        let datetime = DateTime::parse_from_rfc3339(datetime_str).unwrap();
-        forecasts.insert(datetime, forecast);
+        let days = weather.get_daily_forecasts(&chrono_tz::Europe::Oslo);
+        let cloudy = Some(WeatherSymbol::from("cloudy").code);
+
+        assert_eq!(days.len(), 11);
+        assert_eq!(days[0].date, NaiveDate::from_ymd_opt(2023, 7, 15).unwrap());
+        // Hourly steps, the 6 hour periods are not added to them
+        assert_eq!(days[1].min_temperature, 11.4);
+        assert_eq!(days[1].max_temperature, 14.8);
+        assert_eq!(days[1].max_wind_speed, 4.6);
+        assert!((days[1].precipitation_amount - 2.1).abs() < 1e-9);
+        assert_eq!(days[1].weather_code, cloudy);
+        // The hourly steps turn into 6 hour steps at 12:00 UTC
+        assert!((days[2].precipitation_amount - 2.1).abs() < 1e-9);
+        assert_eq!(days[2].weather_code, cloudy);
+        // 6 hour steps only
+        assert!((days[6].precipitation_amount - 4.4).abs() < 1e-9);
+        assert_eq!(days[10].weather_code, cloudy);
     }
 
-    forecasts
+    #[test]
+    fn days_follow_the_daylight_saving_time() {
+        let forecast = metno::parse_response(include_str!("../tests/fixtures/response.json"))
+            .unwrap()
+            .into_values()
+            .next()
+            .unwrap();
+        // Oslo moves from UTC+2 to UTC+1 at 01:00 UTC on 29 October
+        let forecasts = ["2023-10-28T12:00:00Z", "2023-10-29T22:30:00Z"]
+            .into_iter()
+            .map(|time| {
+                (
+                    DateTime::parse_from_rfc3339(time).unwrap(),
+                    forecast.clone(),
+                )
+            })
+            .collect();
+        let weather = Weather {
+            forecasts,
+            coordinates: GeoCoordinate::new(59.91, 10.75),
+        };
+
+        let dates: Vec<NaiveDate> = weather
+            .get_daily_forecasts(&chrono_tz::Europe::Oslo)
+            .iter()
+            .map(|day| day.date)
+            .collect();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2023, 10, 28).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 10, 29).unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn forecast_is_interpolated_between_the_points() {
+        let weather = Weather {
+            forecasts: metno::parse_response(include_str!("../tests/fixtures/response.json"))
+                .unwrap(),
+            coordinates: GeoCoordinate::new(59.91, 10.75),
+        };
+        let from = DateTime::parse_from_rfc3339("2023-07-15T08:00:00Z").unwrap();
+        let till = from + Duration::hours(1);
+        let (before, after) = (&weather.forecasts[&from], &weather.forecasts[&till]);
+
+        let time =
+            (from + Duration::minutes(15)).with_timezone(&FixedOffset::east_opt(3 * 3600).unwrap());
+        let forecast = weather.get_forecast_at(&time).unwrap();
+        let expected = before.air_temperature * 0.75 + after.air_temperature * 0.25;
+        assert!((forecast.air_temperature - expected).abs() < 1e-9);
+        assert_eq!(
+            weather.get_forecast_at(&from).unwrap().air_temperature,
+            before.air_temperature
+        );
+    }
+
+    #[test]
+    fn wind_direction_is_interpolated_on_the_circle() {
+        assert_eq!(lerp_direction(350.0, 10.0, 0.5), 0.0);
+        assert_eq!(lerp_direction(10.0, 350.0, 0.25), 5.0);
+        assert_eq!(lerp_direction(90.0, 180.0, 0.5), 135.0);
+        assert_eq!(lerp_option(Some(1.0), None, 0.25), Some(1.0));
+        assert_eq!(lerp_option(Some(1.0), None, 0.75), None);
+    }
+
+    #[test]
+    fn time_outside_of_the_horizon_is_an_error() {
+        let weather = Weather {
+            forecasts: metno::parse_response(include_str!("../tests/fixtures/response.json"))
+                .unwrap(),
+            coordinates: GeoCoordinate::new(59.91, 10.75),
+        };
+        let till = *weather.forecasts.keys().max().unwrap();
+
+        match weather.get_forecast_at(&(till + Duration::hours(1))) {
+            Err(RequestError::OutOfHorizon { till: last, .. }) => assert_eq!(last, till),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(
+            Weather {
+                forecasts: Forecasts::new(),
+                coordinates: GeoCoordinate::new(59.91, 10.75),
+            }
+            .get_forecast_at(&till),
+            Err(RequestError::NoForecast(_))
+        ));
+    }
+
+    #[test]
+    fn cache_key_is_rounded_to_4_decimals() {
+        let key = CacheKey::new(&GeoCoordinate::new(59.912731, 10.7460923));
+
+        assert_eq!(key.latitude, "59.9127");
+        assert_eq!(key.longitude, "10.7461");
+        assert_eq!(key, CacheKey::new(&GeoCoordinate::new(59.91274, 10.74605)));
+    }
+
+    #[test]
+    fn cached_response_is_fresh_until_expires() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            EXPIRES,
+            HeaderValue::from_static("Sat, 15 Jul 2023 09:13:55 GMT"),
+        );
+        let expires = header_date(&headers, EXPIRES);
+        assert_eq!(
+            expires,
+            Some(Utc.with_ymd_and_hms(2023, 7, 15, 9, 13, 55).unwrap())
+        );
+
+        let cached = CachedResponse {
+            body: String::new(),
+            expires,
+            last_modified: None,
+        };
+        assert!(cached.is_fresh(&Utc.with_ymd_and_hms(2023, 7, 15, 9, 0, 0).unwrap()));
+        assert!(!cached.is_fresh(&Utc.with_ymd_and_hms(2023, 7, 15, 9, 14, 0).unwrap()));
+        assert!(header_date(&HeaderMap::new(), EXPIRES).is_none());
+    }
 }
@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+/// Weather symbols of the met.no legend without the variant suffix
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum WeatherCode {
     ClearSky,
     Cloudy,
@@ -25,75 +27,433 @@ pub enum WeatherCode {
     LightSleet,
     LightSleetAndThunder,
     LightSleetShowers,
+    LightSleetShowersAndThunder,
     LightSnow,
     LightSnowAndThunder,
     LightSnowShowers,
-    LightsSleetShowersAndThunder,
-    LightsSnowShowersAndThunder,
+    LightSnowShowersAndThunder,
     PartlyCloudy,
     Rain,
     RainAndThunder,
     RainShowers,
     RainShowersAndThunder,
     Sleet,
+    SleetAndThunder,
+    SleetShowers,
+    SleetShowersAndThunder,
+    Snow,
+    SnowAndThunder,
+    SnowShowers,
+    SnowShowersAndThunder,
+    /// A code which is not in the legend
+    Unknown(String),
 }
 
-pub fn get_weather_code(string_code: &str) -> WeatherCode {
-    let mut weather_codes = HashMap::new();
-    weather_codes.insert("clearsky", WeatherCode::ClearSky);
-    weather_codes.insert("cloudy", WeatherCode::Cloudy);
-    weather_codes.insert("fair", WeatherCode::Fair);
-    weather_codes.insert("fog", WeatherCode::Fog);
-    weather_codes.insert("heavyrain", WeatherCode::HeavyRain);
-    weather_codes.insert("heavyrainandthunder", WeatherCode::HeavyRainAndThunder);
-    weather_codes.insert("heavyrainshowers", WeatherCode::HeavyRainShowers);
-    weather_codes.insert(
-        "heavyrainshowersandthunder",
+/// Variant of the met.no symbol by the sun position, `_day`, `_night` or `_polartwilight` suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolVariant {
+    Day,
+    Night,
+    PolarTwilight,
+}
+
+/// Weather code with its variant as it comes in the met.no `symbol_code`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WeatherSymbol {
+    pub code: WeatherCode,
+    pub variant: Option<SymbolVariant>,
+}
+
+impl WeatherCode {
+    /// All codes of the met.no legend
+    pub const LEGEND: [WeatherCode; 41] = [
+        WeatherCode::ClearSky,
+        WeatherCode::Cloudy,
+        WeatherCode::Fair,
+        WeatherCode::Fog,
+        WeatherCode::HeavyRain,
+        WeatherCode::HeavyRainAndThunder,
+        WeatherCode::HeavyRainShowers,
         WeatherCode::HeavyRainShowersAndThunder,
-    );
-    weather_codes.insert("heavysleet", WeatherCode::HeavySleet);
-    weather_codes.insert("heavysleetandthunder", WeatherCode::HeavySleetAndThunder);
-    weather_codes.insert("heavysleetshowers", WeatherCode::HeavySleetShowers);
-    weather_codes.insert(
-        "heavysleetshowersandthunder",
+        WeatherCode::HeavySleet,
+        WeatherCode::HeavySleetAndThunder,
+        WeatherCode::HeavySleetShowers,
         WeatherCode::HeavySleetShowersAndThunder,
-    );
-    weather_codes.insert("heavysnow", WeatherCode::HeavySnow);
-    weather_codes.insert("heavysnowandthunder", WeatherCode::HeavySnowAndThunder);
-    weather_codes.insert("heavysnowshowers", WeatherCode::HeavySnowShowers);
-    weather_codes.insert(
-        "heavysnowshowersandthunder",
+        WeatherCode::HeavySnow,
+        WeatherCode::HeavySnowAndThunder,
+        WeatherCode::HeavySnowShowers,
         WeatherCode::HeavySnowShowersAndThunder,
-    );
-    weather_codes.insert("lightrain", WeatherCode::LightRain);
-    weather_codes.insert("lightrainshowers", WeatherCode::LightRainShowers);
-    weather_codes.insert(
-        "lightrainshowersandthunder",
+        WeatherCode::LightRain,
+        WeatherCode::LightRainAndThunder,
+        WeatherCode::LightRainShowers,
         WeatherCode::LightRainShowersAndThunder,
-    );
-    weather_codes.insert("lightsleet", WeatherCode::LightSleet);
-    weather_codes.insert("lightsleetandthunder", WeatherCode::LightSleetAndThunder);
-    weather_codes.insert("lightsleetshowers", WeatherCode::LightSleetShowers);
-    weather_codes.insert("lightsnowandthunder", WeatherCode::LightSnowAndThunder);
-    weather_codes.insert("lightsnowshowers", WeatherCode::LightSnowShowers);
-    weather_codes.insert(
-        "lightssleetshowersandthunder",
-        WeatherCode::LightsSleetShowersAndThunder,
-    );
-    weather_codes.insert(
-        "lightssnowshowersandthunder",
-        WeatherCode::LightsSnowShowersAndThunder,
-    );
-    weather_codes.insert("partlycloudy", WeatherCode::PartlyCloudy);
-    weather_codes.insert("rain", WeatherCode::Rain);
-    weather_codes.insert("rainandthunder", WeatherCode::RainAndThunder);
-    weather_codes.insert("rainshowers", WeatherCode::RainShowers);
-    weather_codes.insert("rainshowersandthunder", WeatherCode::RainShowersAndThunder);
-    weather_codes.insert("sleet", WeatherCode::Sleet);
-
-    if let Some(code) = weather_codes.get(string_code) {
-        *code
-    } else {
-        WeatherCode::ClearSky
+        WeatherCode::LightSleet,
+        WeatherCode::LightSleetAndThunder,
+        WeatherCode::LightSleetShowers,
+        WeatherCode::LightSleetShowersAndThunder,
+        WeatherCode::LightSnow,
+        WeatherCode::LightSnowAndThunder,
+        WeatherCode::LightSnowShowers,
+        WeatherCode::LightSnowShowersAndThunder,
+        WeatherCode::PartlyCloudy,
+        WeatherCode::Rain,
+        WeatherCode::RainAndThunder,
+        WeatherCode::RainShowers,
+        WeatherCode::RainShowersAndThunder,
+        WeatherCode::Sleet,
+        WeatherCode::SleetAndThunder,
+        WeatherCode::SleetShowers,
+        WeatherCode::SleetShowersAndThunder,
+        WeatherCode::Snow,
+        WeatherCode::SnowAndThunder,
+        WeatherCode::SnowShowers,
+        WeatherCode::SnowShowersAndThunder,
+    ];
+
+    /// The code as met.no spells it
+    pub fn as_str(&self) -> &str {
+        match self {
+            WeatherCode::ClearSky => "clearsky",
+            WeatherCode::Cloudy => "cloudy",
+            WeatherCode::Fair => "fair",
+            WeatherCode::Fog => "fog",
+            WeatherCode::HeavyRain => "heavyrain",
+            WeatherCode::HeavyRainAndThunder => "heavyrainandthunder",
+            WeatherCode::HeavyRainShowers => "heavyrainshowers",
+            WeatherCode::HeavyRainShowersAndThunder => "heavyrainshowersandthunder",
+            WeatherCode::HeavySleet => "heavysleet",
+            WeatherCode::HeavySleetAndThunder => "heavysleetandthunder",
+            WeatherCode::HeavySleetShowers => "heavysleetshowers",
+            WeatherCode::HeavySleetShowersAndThunder => "heavysleetshowersandthunder",
+            WeatherCode::HeavySnow => "heavysnow",
+            WeatherCode::HeavySnowAndThunder => "heavysnowandthunder",
+            WeatherCode::HeavySnowShowers => "heavysnowshowers",
+            WeatherCode::HeavySnowShowersAndThunder => "heavysnowshowersandthunder",
+            WeatherCode::LightRain => "lightrain",
+            WeatherCode::LightRainAndThunder => "lightrainandthunder",
+            WeatherCode::LightRainShowers => "lightrainshowers",
+            WeatherCode::LightRainShowersAndThunder => "lightrainshowersandthunder",
+            WeatherCode::LightSleet => "lightsleet",
+            WeatherCode::LightSleetAndThunder => "lightsleetandthunder",
+            WeatherCode::LightSleetShowers => "lightsleetshowers",
+            // The legend really spells these two with "lights"
+            WeatherCode::LightSleetShowersAndThunder => "lightssleetshowersandthunder",
+            WeatherCode::LightSnow => "lightsnow",
+            WeatherCode::LightSnowAndThunder => "lightsnowandthunder",
+            WeatherCode::LightSnowShowers => "lightsnowshowers",
+            WeatherCode::LightSnowShowersAndThunder => "lightssnowshowersandthunder",
+            WeatherCode::PartlyCloudy => "partlycloudy",
+            WeatherCode::Rain => "rain",
+            WeatherCode::RainAndThunder => "rainandthunder",
+            WeatherCode::RainShowers => "rainshowers",
+            WeatherCode::RainShowersAndThunder => "rainshowersandthunder",
+            WeatherCode::Sleet => "sleet",
+            WeatherCode::SleetAndThunder => "sleetandthunder",
+            WeatherCode::SleetShowers => "sleetshowers",
+            WeatherCode::SleetShowersAndThunder => "sleetshowersandthunder",
+            WeatherCode::Snow => "snow",
+            WeatherCode::SnowAndThunder => "snowandthunder",
+            WeatherCode::SnowShowers => "snowshowers",
+            WeatherCode::SnowShowersAndThunder => "snowshowersandthunder",
+            WeatherCode::Unknown(code) => code,
+        }
+    }
+
+    /// Whether the code comes with the `_day`/`_night`/`_polartwilight` suffix
+    pub fn has_variants(&self) -> bool {
+        matches!(
+            self,
+            WeatherCode::ClearSky
+                | WeatherCode::Fair
+                | WeatherCode::PartlyCloudy
+                | WeatherCode::HeavyRainShowers
+                | WeatherCode::HeavyRainShowersAndThunder
+                | WeatherCode::HeavySleetShowers
+                | WeatherCode::HeavySleetShowersAndThunder
+                | WeatherCode::HeavySnowShowers
+                | WeatherCode::HeavySnowShowersAndThunder
+                | WeatherCode::LightRainShowers
+                | WeatherCode::LightRainShowersAndThunder
+                | WeatherCode::LightSleetShowers
+                | WeatherCode::LightSleetShowersAndThunder
+                | WeatherCode::LightSnowShowers
+                | WeatherCode::LightSnowShowersAndThunder
+                | WeatherCode::RainShowers
+                | WeatherCode::RainShowersAndThunder
+                | WeatherCode::SleetShowers
+                | WeatherCode::SleetShowersAndThunder
+                | WeatherCode::SnowShowers
+                | WeatherCode::SnowShowersAndThunder
+        )
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            WeatherCode::ClearSky => "clear sky",
+            WeatherCode::Cloudy => "cloudy",
+            WeatherCode::Fair => "fair",
+            WeatherCode::Fog => "fog",
+            WeatherCode::HeavyRain => "heavy rain",
+            WeatherCode::HeavyRainAndThunder => "heavy rain and thunder",
+            WeatherCode::HeavyRainShowers => "heavy rain showers",
+            WeatherCode::HeavyRainShowersAndThunder => "heavy rain showers and thunder",
+            WeatherCode::HeavySleet => "heavy sleet",
+            WeatherCode::HeavySleetAndThunder => "heavy sleet and thunder",
+            WeatherCode::HeavySleetShowers => "heavy sleet showers",
+            WeatherCode::HeavySleetShowersAndThunder => "heavy sleet showers and thunder",
+            WeatherCode::HeavySnow => "heavy snow",
+            WeatherCode::HeavySnowAndThunder => "heavy snow and thunder",
+            WeatherCode::HeavySnowShowers => "heavy snow showers",
+            WeatherCode::HeavySnowShowersAndThunder => "heavy snow showers and thunder",
+            WeatherCode::LightRain => "light rain",
+            WeatherCode::LightRainAndThunder => "light rain and thunder",
+            WeatherCode::LightRainShowers => "light rain showers",
+            WeatherCode::LightRainShowersAndThunder => "light rain showers and thunder",
+            WeatherCode::LightSleet => "light sleet",
+            WeatherCode::LightSleetAndThunder => "light sleet and thunder",
+            WeatherCode::LightSleetShowers => "light sleet showers",
+            WeatherCode::LightSleetShowersAndThunder => "light sleet showers and thunder",
+            WeatherCode::LightSnow => "light snow",
+            WeatherCode::LightSnowAndThunder => "light snow and thunder",
+            WeatherCode::LightSnowShowers => "light snow showers",
+            WeatherCode::LightSnowShowersAndThunder => "light snow showers and thunder",
+            WeatherCode::PartlyCloudy => "partly cloudy",
+            WeatherCode::Rain => "rain",
+            WeatherCode::RainAndThunder => "rain and thunder",
+            WeatherCode::RainShowers => "rain showers",
+            WeatherCode::RainShowersAndThunder => "rain showers and thunder",
+            WeatherCode::Sleet => "sleet",
+            WeatherCode::SleetAndThunder => "sleet and thunder",
+            WeatherCode::SleetShowers => "sleet showers",
+            WeatherCode::SleetShowersAndThunder => "sleet showers and thunder",
+            WeatherCode::Snow => "snow",
+            WeatherCode::SnowAndThunder => "snow and thunder",
+            WeatherCode::SnowShowers => "snow showers",
+            WeatherCode::SnowShowersAndThunder => "snow showers and thunder",
+            WeatherCode::Unknown(_) => "unknown weather",
+        }
+    }
+
+    pub fn emoji(&self, variant: Option<SymbolVariant>) -> &'static str {
+        let night = matches!(variant, Some(SymbolVariant::Night));
+        let twilight = matches!(variant, Some(SymbolVariant::PolarTwilight));
+        match self {
+            WeatherCode::ClearSky | WeatherCode::Fair if night => "🌙",
+            WeatherCode::ClearSky | WeatherCode::Fair if twilight => "🌅",
+            WeatherCode::ClearSky => "☀️",
+            WeatherCode::Fair => "🌤",
+            WeatherCode::PartlyCloudy if night => "☁️",
+            WeatherCode::PartlyCloudy => "⛅",
+            WeatherCode::Cloudy => "☁️",
+            WeatherCode::Fog => "🌫",
+            WeatherCode::LightRainShowers
+            | WeatherCode::RainShowers
+            | WeatherCode::HeavyRainShowers
+                if !night =>
+            {
+                "🌦"
+            }
+            WeatherCode::LightRain
+            | WeatherCode::Rain
+            | WeatherCode::HeavyRain
+            | WeatherCode::LightRainShowers
+            | WeatherCode::RainShowers
+            | WeatherCode::HeavyRainShowers => "🌧",
+            WeatherCode::LightSleet
+            | WeatherCode::Sleet
+            | WeatherCode::HeavySleet
+            | WeatherCode::LightSleetShowers
+            | WeatherCode::SleetShowers
+            | WeatherCode::HeavySleetShowers => "🌨",
+            WeatherCode::LightSnow
+            | WeatherCode::Snow
+            | WeatherCode::HeavySnow
+            | WeatherCode::LightSnowShowers
+            | WeatherCode::SnowShowers
+            | WeatherCode::HeavySnowShowers => "❄️",
+            WeatherCode::HeavyRainAndThunder
+            | WeatherCode::HeavyRainShowersAndThunder
+            | WeatherCode::HeavySleetAndThunder
+            | WeatherCode::HeavySleetShowersAndThunder
+            | WeatherCode::HeavySnowAndThunder
+            | WeatherCode::HeavySnowShowersAndThunder
+            | WeatherCode::LightRainAndThunder
+            | WeatherCode::LightRainShowersAndThunder
+            | WeatherCode::LightSleetAndThunder
+            | WeatherCode::LightSleetShowersAndThunder
+            | WeatherCode::LightSnowAndThunder
+            | WeatherCode::LightSnowShowersAndThunder
+            | WeatherCode::RainAndThunder
+            | WeatherCode::RainShowersAndThunder
+            | WeatherCode::SleetAndThunder
+            | WeatherCode::SleetShowersAndThunder
+            | WeatherCode::SnowAndThunder
+            | WeatherCode::SnowShowersAndThunder => "⛈",
+            WeatherCode::Unknown(_) => "❔",
+        }
+    }
+}
+
+impl From<&str> for WeatherCode {
+    fn from(string_code: &str) -> Self {
+        match string_code {
+            // Accept the spelling without the typo of the legend as well
+            "lightsleetshowersandthunder" => WeatherCode::LightSleetShowersAndThunder,
+            "lightsnowshowersandthunder" => WeatherCode::LightSnowShowersAndThunder,
+            _ => WeatherCode::LEGEND
+                .into_iter()
+                .find(|code| code.as_str() == string_code)
+                .unwrap_or_else(|| WeatherCode::Unknown(string_code.to_string())),
+        }
+    }
+}
+
+impl FromStr for WeatherCode {
+    type Err = Infallible;
+
+    fn from_str(string_code: &str) -> Result<Self, Self::Err> {
+        Ok(WeatherCode::from(string_code))
+    }
+}
+
+impl std::fmt::Display for WeatherCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl SymbolVariant {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymbolVariant::Day => "day",
+            SymbolVariant::Night => "night",
+            SymbolVariant::PolarTwilight => "polartwilight",
+        }
+    }
+}
+
+impl WeatherSymbol {
+    pub fn emoji(&self) -> &'static str {
+        self.code.emoji(self.variant)
+    }
+
+    /// Human readable text of the symbol with its emoji
+    pub fn describe(&self) -> String {
+        format!("{} {}", self.emoji(), self.code.description())
+    }
+}
+
+impl From<&str> for WeatherSymbol {
+    fn from(symbol_code: &str) -> Self {
+        let (string_code, variant) = match symbol_code.rsplit_once('_') {
+            Some((code, "day")) => (code, Some(SymbolVariant::Day)),
+            Some((code, "night")) => (code, Some(SymbolVariant::Night)),
+            Some((code, "polartwilight")) => (code, Some(SymbolVariant::PolarTwilight)),
+            _ => (symbol_code, None),
+        };
+
+        WeatherSymbol {
+            code: WeatherCode::from(string_code),
+            variant,
+        }
+    }
+}
+
+impl FromStr for WeatherSymbol {
+    type Err = Infallible;
+
+    fn from_str(symbol_code: &str) -> Result<Self, Self::Err> {
+        Ok(WeatherSymbol::from(symbol_code))
+    }
+}
+
+/// Formats back to the met.no `symbol_code`
+impl std::fmt::Display for WeatherSymbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.variant {
+            Some(variant) => write!(f, "{}_{}", self.code, variant.as_str()),
+            None => write!(f, "{}", self.code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `symbol_code,has_variants` rows of the met.no weather icons legend
+    const LEGEND_CSV: &str = include_str!("../tests/fixtures/symbol_legend.csv");
+
+    fn legend() -> Vec<(&'static str, bool)> {
+        LEGEND_CSV
+            .lines()
+            .skip(1)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (code, variants) = line.split_once(',').unwrap();
+                (code, variants == "1")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn legend_is_covered() {
+        let legend = legend();
+        assert_eq!(legend.len(), WeatherCode::LEGEND.len());
+
+        for (string_code, has_variants) in legend {
+            let code: WeatherCode = string_code.parse().unwrap();
+            assert!(
+                !matches!(code, WeatherCode::Unknown(_)),
+                "{} is unknown",
+                string_code
+            );
+            assert_eq!(code.to_string(), string_code);
+            assert_eq!(code.has_variants(), has_variants, "{}", string_code);
+        }
+    }
+
+    #[test]
+    fn variants_are_parsed() {
+        for (string_code, has_variants) in legend().into_iter().filter(|(_, v)| *v) {
+            assert!(has_variants);
+            for variant in [
+                SymbolVariant::Day,
+                SymbolVariant::Night,
+                SymbolVariant::PolarTwilight,
+            ] {
+                let symbol_code = format!("{}_{}", string_code, variant.as_str());
+                let symbol: WeatherSymbol = symbol_code.parse().unwrap();
+                assert_eq!(symbol.code.as_str(), string_code);
+                assert_eq!(symbol.variant, Some(variant));
+                assert_eq!(symbol.to_string(), symbol_code);
+            }
+        }
+    }
+
+    #[test]
+    fn misspelled_legend_codes() {
+        assert_eq!(
+            WeatherCode::from("lightsleetshowersandthunder"),
+            WeatherCode::LightSleetShowersAndThunder
+        );
+        assert_eq!(
+            WeatherCode::from("lightssnowshowersandthunder"),
+            WeatherCode::LightSnowShowersAndThunder
+        );
+    }
+
+    #[test]
+    fn unknown_code() {
+        let symbol = WeatherSymbol::from("meteorshower_night");
+        assert_eq!(
+            symbol.code,
+            WeatherCode::Unknown("meteorshower".to_string())
+        );
+        assert_eq!(symbol.variant, Some(SymbolVariant::Night));
+        assert_eq!(
+            WeatherSymbol::from("").code,
+            WeatherCode::Unknown(String::new())
+        );
     }
 }
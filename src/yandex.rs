@@ -0,0 +1,162 @@
+//! Models of the Yandex geocoder JSON response.
+//! See <https://yandex.com/maps-api/docs/geocoder-api/response.html>
+
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeocoderResponse {
+    pub response: ResponseBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseBody {
+    #[serde(rename = "GeoObjectCollection")]
+    pub geo_object_collection: GeoObjectCollection,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoObjectCollection {
+    pub meta_data_property: CollectionMetaData,
+    /// Missing when nothing was found
+    #[serde(default)]
+    pub feature_member: Vec<FeatureMember>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CollectionMetaData {
+    #[serde(rename = "GeocoderResponseMetaData")]
+    pub geocoder_response_meta_data: GeocoderResponseMetaData,
+}
+
+/// The numbers come as strings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeocoderResponseMetaData {
+    /// The query as it was understood
+    pub request: String,
+    /// Total number of the found objects, not only the returned ones
+    #[serde(deserialize_with = "number_from_string")]
+    pub found: u32,
+    #[serde(deserialize_with = "number_from_string")]
+    pub results: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureMember {
+    #[serde(rename = "GeoObject")]
+    pub geo_object: GeoObject,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeoObject {
+    pub meta_data_property: ObjectMetaData,
+    pub name: String,
+    /// Region and country
+    pub description: Option<String>,
+    pub bounded_by: Option<BoundedBy>,
+    #[serde(rename = "Point")]
+    pub point: Point,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectMetaData {
+    #[serde(rename = "GeocoderMetaData")]
+    pub geocoder_meta_data: GeocoderMetaData,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeocoderMetaData {
+    /// How exactly the object matches the query, e.g. `exact` or `other`
+    pub precision: Option<String>,
+    /// Full formatted address
+    pub text: Option<String>,
+    /// Type of the object, e.g. `locality` or `street`
+    pub kind: Option<String>,
+    #[serde(rename = "Address")]
+    pub address: Option<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Address {
+    /// ISO 3166-1 alpha-2 code
+    pub country_code: Option<String>,
+    pub formatted: Option<String>,
+    /// From the country down to the house
+    #[serde(rename = "Components", default)]
+    pub components: Vec<AddressComponent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddressComponent {
+    pub kind: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoundedBy {
+    #[serde(rename = "Envelope")]
+    pub envelope: Envelope,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub lower_corner: Position,
+    pub upper_corner: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub pos: Position,
+}
+
+/// Point written as `"longitude latitude"`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+impl std::str::FromStr for Position {
+    type Err = String;
+
+    fn from_str(pos: &str) -> Result<Self, Self::Err> {
+        let parse_error = || format!("invalid position \"{}\"", pos);
+        let (longitude, latitude) = pos.trim().split_once(' ').ok_or_else(parse_error)?;
+        Ok(Position {
+            longitude: longitude.parse().map_err(|_| parse_error())?,
+            latitude: latitude.trim().parse().map_err(|_| parse_error())?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{} {}", self.longitude, self.latitude))
+    }
+}
+
+fn number_from_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Number(u32),
+        String(String),
+    }
+
+    match Number::deserialize(deserializer)? {
+        Number::Number(number) => Ok(number),
+        Number::String(text) => text.trim().parse().map_err(de::Error::custom),
+    }
+}